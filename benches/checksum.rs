@@ -0,0 +1,27 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use pngme::checksum::{Crc32, Portable, SliceBy8};
+
+
+fn bench_backend<C: Crc32>(c: &mut Criterion, name: &str, bytes: &[u8])
+{
+    let mut group = c.benchmark_group("checksum");
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.bench_function(name, |b| b.iter(|| C::checksum(&[bytes])));
+    group.finish();
+}
+
+
+fn checksum(c: &mut Criterion)
+{
+    // The size of a typical IDAT chunk
+    let bytes: Vec<u8> = (0..8192u32).map(|i| (i * 31 + 7) as u8).collect();
+
+    bench_backend::<Portable>(c, "Portable", &bytes);
+    bench_backend::<SliceBy8>(c, "SliceBy8", &bytes);
+    #[cfg(feature = "crc-simd")]
+    bench_backend::<pngme::checksum::Simd>(c, "Simd", &bytes);
+}
+
+
+criterion_group!(benches, checksum);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use pngme::pixels::Image;
+use pngme::png::Png;
+use pngme::png_ref::PngRef;
+
+
+/// A large PNG made of noise so that the IDAT data barely compresses
+fn testing_png_bytes() -> Vec<u8>
+{
+    let mut image = Image::new(1024, 1024);
+    let mut state: u32 = 0x12345678;
+    for byte in image.pixels.iter_mut()
+    {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        *byte = state as u8;
+    }
+
+    image.to_png().unwrap().as_bytes()
+}
+
+
+fn parse(c: &mut Criterion)
+{
+    let bytes = testing_png_bytes();
+
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+    group.bench_function("Png::try_from", |b| b.iter(|| Png::try_from(&bytes[..]).unwrap()));
+    group.bench_function("PngRef::try_from", |b| b.iter(|| PngRef::try_from(&bytes[..]).unwrap()));
+
+    group.finish();
+}
+
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
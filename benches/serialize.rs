@@ -0,0 +1,61 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use pngme::pixels::Image;
+use pngme::png::Png;
+
+
+/// A large PNG made of noise, split into many IDAT chunks like most encoders do
+fn testing_png() -> Png
+{
+    let mut image = Image::new(1024, 1024);
+    let mut state: u32 = 0x12345678;
+    for byte in image.pixels.iter_mut()
+    {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        *byte = state as u8;
+    }
+
+    let png = image.to_png().unwrap();
+    let bytes = png.as_bytes();
+
+    // Re-chunk the single IDAT into 8 KiB pieces
+    let idat = png.chunk_by_type("IDAT").unwrap().as_bytes();
+    let data = &idat[8..idat.len() - 4];
+    let mut rechunked = bytes[..33].to_vec();
+    for piece in data.chunks(8192)
+    {
+        let mut chunk = (piece.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(b"IDAT");
+        chunk.extend_from_slice(piece);
+        let crc = crc::crc32::checksum_ieee(&chunk[4..]);
+        chunk.extend_from_slice(&crc.to_be_bytes());
+        rechunked.extend(chunk);
+    }
+    rechunked.extend_from_slice(&bytes[bytes.len() - 12..]);
+
+    Png::try_from(&rechunked[..]).unwrap()
+}
+
+
+fn serialize(c: &mut Criterion)
+{
+    let png = testing_png();
+
+    let mut group = c.benchmark_group("serialize");
+    group.throughput(Throughput::Bytes(png.byte_len() as u64));
+
+    group.bench_function("Png::as_bytes", |b| b.iter(|| png.as_bytes()));
+    let mut buffer = Vec::with_capacity(png.byte_len());
+    group.bench_function("Png::write_to", |b| b.iter(|| {
+        buffer.clear();
+        png.write_to(&mut buffer).unwrap();
+        black_box(&buffer);
+    }));
+
+    group.finish();
+}
+
+
+criterion_group!(benches, serialize);
+criterion_main!(benches);
@@ -0,0 +1,496 @@
+use std::path::PathBuf;
+use clap::{Subcommand, Args, AppSettings};
+use pngme::watermark::WatermarkOptions;
+
+#[derive(Subcommand)]
+pub enum PngMeArgs
+{
+    /// Encode a message in a PNG file
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Encode(Box<EncodeArgs>),
+
+    /// Decode a message from a PNG file
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Decode(DecodeArgs),
+
+    /// Remove a message from a PNG file
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Remove(RemoveArgs),
+
+    /// Display all chunks in a PNG file
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Print(PrintArgs),
+
+    /// Rewrite wrong chunk CRCs in PNG files
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    RepairCrc(RepairCrcArgs),
+
+    /// Report private, unknown or appended data in PNG files
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Scan(ScanArgs),
+
+    /// Report signs of hidden data in the pixels and ancillary chunks of PNG files
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Analyze(AnalyzeArgs),
+
+    /// Split or assemble animated PNG files
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Apng(ApngArgs),
+
+    /// Store the digests of all chunks in PNG files
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Seal(SealArgs),
+
+    /// Report chunks changed, moved or missing since PNG files were sealed
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Check(CheckArgs),
+
+    /// Sign all chunks of PNG files
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Sign(SignArgs),
+
+    /// Check the signature of PNG files and report chunks changed since signing
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Verify(VerifyArgs),
+
+    /// Embed an invisible watermark ID in the pixels of PNG files
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Watermark(WatermarkArgs),
+
+    /// Look for a watermark in the pixels of PNG files
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Detect(DetectArgs),
+
+    /// Generate an identity for messages encrypted to recipients, or a signing key
+    Keygen(KeygenArgs),
+}
+
+
+#[derive(Args)]
+pub struct EncodeArgs
+{
+    /// Input files, directories, glob patterns or - for stdin to encode from
+    #[clap(parse(from_os_str), short='i', long = "file-path", required = true)]
+    pub file_paths: Vec<PathBuf>,
+
+    /// Chunk type of the hidden message, or the standard chunk to disguise it as with --camouflage
+    pub chunk_type: String,
+
+    /// The hidden message
+    pub message: String,
+
+    /// Disguise the message as standard metadata: a zTXt raw profile, padding in an iCCP color profile
+    /// or the entries of an sPLT palette, named by the chunk type. Needs a key or a recipient
+    #[clap(long)]
+    pub camouflage: bool,
+
+    /// Key to encrypt message. Visible to other users in the process list,
+    /// prefer --key-file, --key-env or --ask-key
+    #[clap(short, long, conflicts_with_all = &["key-file", "key-env", "ask-key"])]
+    pub encryption_key: Option<String>,
+
+    #[clap(flatten)]
+    pub key: KeyArgs,
+
+    #[clap(flatten)]
+    pub decoy: DecoyArgs,
+
+    /// Public key of a recipient who can decrypt the message, repeat for several recipients
+    #[clap(short, long = "recipient", value_name = "PUBKEY",
+        conflicts_with_all = &["encryption-key", "key-file", "key-env", "ask-key", "decoy-message", "deniable"])]
+    pub recipients: Vec<String>,
+
+    /// Output file of the encoded PNG, - for stdout
+    #[clap(short, long)]
+    pub out_file: Option<PathBuf>,
+
+    /// Output directory mirroring the input tree
+    #[clap(parse(from_os_str), long, conflicts_with = "out-file")]
+    pub output_dir: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub in_place: InPlaceArgs,
+
+    #[clap(flatten)]
+    pub trailer: TrailerArgs,
+}
+
+
+#[derive(Args)]
+pub struct DecodeArgs
+{
+    /// Input files, directories, glob patterns or - for stdin to decode from
+    #[clap(parse(from_os_str), short='i', long = "file-path", required = true)]
+    pub file_paths: Vec<PathBuf>,
+
+    /// Chunk type of the hidden message to decode, or the standard chunk it is disguised as with --camouflage
+    pub chunk_type: String,
+
+    /// Read a message disguised as standard metadata by encode --camouflage
+    #[clap(long)]
+    pub camouflage: bool,
+
+    /// Key to decrypt message. Visible to other users in the process list,
+    /// prefer --key-file, --key-env or --ask-key
+    #[clap(short, long, conflicts_with_all = &["key-file", "key-env", "ask-key"])]
+    pub decryption_key: Option<String>,
+
+    #[clap(flatten)]
+    pub key: KeyArgs,
+
+    /// Identity file of a recipient of the message, repeat to try several
+    #[clap(parse(from_os_str), long = "identity", value_name = "KEYFILE",
+        conflicts_with_all = &["decryption-key", "key-file", "key-env", "ask-key"])]
+    pub identities: Vec<PathBuf>,
+}
+
+
+#[derive(Args)]
+pub struct RemoveArgs
+{
+    /// Input files, directories, glob patterns or - for stdin to remove a secret message from
+    #[clap(parse(from_os_str), short='i', long = "file-path", required = true)]
+    pub file_paths: Vec<PathBuf>,
+
+    /// Chunk type of the hidden message to remove
+    pub chunk_type: String,
+
+    /// Output file of the PNG without the message, - for stdout
+    #[clap(short, long)]
+    pub out_file: Option<PathBuf>,
+
+    /// Output directory mirroring the input tree
+    #[clap(parse(from_os_str), long, conflicts_with = "out-file")]
+    pub output_dir: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub in_place: InPlaceArgs,
+
+    #[clap(flatten)]
+    pub trailer: TrailerArgs,
+}
+
+
+#[derive(Args)]
+pub struct PrintArgs
+{
+    /// Input files, directories, glob patterns or - for stdin to print PNG chunks from
+    #[clap(parse(from_os_str), short='i', long = "file-path", required = true)]
+    pub file_paths: Vec<PathBuf>,
+
+    /// Save any data appended after IEND to a file
+    #[clap(parse(from_os_str), long)]
+    pub save_trailer: Option<PathBuf>,
+
+    /// Print only the image header, without reading the rest of the file
+    #[clap(long, conflicts_with = "save-trailer")]
+    pub header: bool,
+}
+
+
+#[derive(Args)]
+pub struct RepairCrcArgs
+{
+    /// Input files, directories, glob patterns or - for stdin to repair
+    #[clap(parse(from_os_str), short='i', long = "file-path", required = true)]
+    pub file_paths: Vec<PathBuf>,
+
+    /// Output file of the repaired PNG, - for stdout
+    #[clap(short, long)]
+    pub out_file: Option<PathBuf>,
+
+    /// Output directory mirroring the input tree
+    #[clap(parse(from_os_str), long, conflicts_with = "out-file")]
+    pub output_dir: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub in_place: InPlaceArgs,
+}
+
+
+#[derive(Args)]
+pub struct InPlaceArgs
+{
+    /// Modify the input files in place
+    #[clap(long, conflicts_with_all = &["out-file", "output-dir"])]
+    pub in_place: bool,
+
+    /// Keep a copy of each original file with this suffix appended, e.g. .bak. Existing copies are not overwritten
+    #[clap(long, requires = "in-place")]
+    pub backup_suffix: Option<String>,
+}
+
+
+#[derive(Args)]
+pub struct KeyArgs
+{
+    /// Read the key from a file without its final line break, - for stdin
+    #[clap(parse(from_os_str), long, conflicts_with_all = &["key-env", "ask-key"])]
+    pub key_file: Option<PathBuf>,
+
+    /// Read the key from an environment variable
+    #[clap(long, value_name = "VAR", conflicts_with = "ask-key")]
+    pub key_env: Option<String>,
+
+    /// Prompt for the key without echoing it
+    #[clap(long)]
+    pub ask_key: bool,
+}
+
+
+#[derive(Args)]
+pub struct DecoyArgs
+{
+    /// Message revealed by the decoy key instead of the real one, stored in the deniable format
+    #[clap(long)]
+    pub decoy_message: Option<String>,
+
+    /// Read the decoy key from a file without its final line break, - for stdin
+    #[clap(parse(from_os_str), long, requires = "decoy-message", conflicts_with_all = &["decoy-key-env", "ask-decoy-key"])]
+    pub decoy_key_file: Option<PathBuf>,
+
+    /// Read the decoy key from an environment variable
+    #[clap(long, value_name = "VAR", requires = "decoy-message", conflicts_with = "ask-decoy-key")]
+    pub decoy_key_env: Option<String>,
+
+    /// Prompt for the decoy key without echoing it
+    #[clap(long, requires = "decoy-message")]
+    pub ask_decoy_key: bool,
+
+    /// Store the message in the deniable format of two slots even without a decoy
+    #[clap(long)]
+    pub deniable: bool,
+}
+
+
+#[derive(Args)]
+pub struct TrailerArgs
+{
+    /// Drop any data appended after IEND from the output
+    #[clap(long)]
+    pub strip_trailer: bool,
+
+    /// Save any data appended after IEND to a file
+    #[clap(parse(from_os_str), long)]
+    pub save_trailer: Option<PathBuf>,
+}
+
+
+#[derive(Args)]
+pub struct KeygenArgs
+{
+    /// Output file of the identity, which must not exist yet. Printed to stdout if not given
+    #[clap(parse(from_os_str), short, long)]
+    pub out_file: Option<PathBuf>,
+
+    /// Generate an Ed25519 key for sign and verify instead of an age identity
+    #[clap(long)]
+    pub signing: bool,
+}
+
+
+#[derive(Args)]
+pub struct SealArgs
+{
+    /// Input files, directories, glob patterns or - for stdin to seal
+    #[clap(parse(from_os_str), short='i', long = "file-path", required = true)]
+    pub file_paths: Vec<PathBuf>,
+
+    /// Output file of the sealed PNG, - for stdout
+    #[clap(short, long)]
+    pub out_file: Option<PathBuf>,
+
+    /// Output directory mirroring the input tree
+    #[clap(parse(from_os_str), long, conflicts_with = "out-file")]
+    pub output_dir: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub in_place: InPlaceArgs,
+}
+
+
+#[derive(Args)]
+pub struct CheckArgs
+{
+    /// Input files, directories, glob patterns or - for stdin to check
+    #[clap(parse(from_os_str), short='i', long = "file-path", required = true)]
+    pub file_paths: Vec<PathBuf>,
+}
+
+
+#[derive(Args)]
+pub struct SignArgs
+{
+    /// Input files, directories, glob patterns or - for stdin to sign
+    #[clap(parse(from_os_str), short='i', long = "file-path", required = true)]
+    pub file_paths: Vec<PathBuf>,
+
+    /// Signing key file, as written by keygen --signing
+    #[clap(parse(from_os_str), short, long, value_name = "KEYFILE")]
+    pub key: PathBuf,
+
+    /// Output file of the signed PNG, - for stdout
+    #[clap(short, long)]
+    pub out_file: Option<PathBuf>,
+
+    /// Output directory mirroring the input tree
+    #[clap(parse(from_os_str), long, conflicts_with = "out-file")]
+    pub output_dir: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub in_place: InPlaceArgs,
+}
+
+
+#[derive(Args)]
+pub struct VerifyArgs
+{
+    /// Input files, directories, glob patterns or - for stdin to verify
+    #[clap(parse(from_os_str), short='i', long = "file-path", required = true)]
+    pub file_paths: Vec<PathBuf>,
+
+    /// Public key of a trusted signer in hex, repeat for several signers
+    #[clap(short = 'k', long = "public-key", value_name = "PUBKEY", required = true)]
+    pub public_keys: Vec<String>,
+}
+
+
+#[derive(Args)]
+pub struct WatermarkKeyArgs
+{
+    /// Secret that the watermark pattern is derived from. Visible to other users in the process list,
+    /// prefer --key-file, --key-env or --ask-key
+    #[clap(long, value_name = "KEY", conflicts_with_all = &["key-file", "key-env", "ask-key"])]
+    pub watermark_key: Option<String>,
+
+    #[clap(flatten)]
+    pub key: KeyArgs,
+}
+
+
+#[derive(Args)]
+pub struct WatermarkArgs
+{
+    /// Input files, directories, glob patterns or - for stdin to watermark
+    #[clap(parse(from_os_str), short='i', long = "file-path", required = true)]
+    pub file_paths: Vec<PathBuf>,
+
+    /// ID to embed
+    #[clap(long)]
+    pub id: u32,
+
+    /// Change of luminance out of 255. Higher survives more processing and is more visible
+    #[clap(long, default_value_t = WatermarkOptions::DEFAULT_STRENGTH)]
+    pub strength: u8,
+
+    #[clap(flatten)]
+    pub key: WatermarkKeyArgs,
+
+    /// Output file of the watermarked PNG, - for stdout
+    #[clap(short, long)]
+    pub out_file: Option<PathBuf>,
+
+    /// Output directory mirroring the input tree
+    #[clap(parse(from_os_str), long, conflicts_with = "out-file")]
+    pub output_dir: Option<PathBuf>,
+
+    #[clap(flatten)]
+    pub in_place: InPlaceArgs,
+}
+
+
+#[derive(Args)]
+pub struct DetectArgs
+{
+    /// Input files, directories, glob patterns or - for stdin to look for a watermark in
+    #[clap(parse(from_os_str), short='i', long = "file-path", required = true)]
+    pub file_paths: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    pub key: WatermarkKeyArgs,
+}
+
+
+#[derive(Args)]
+pub struct ScanArgs
+{
+    /// Files, directories, glob patterns or - for stdin to scan
+    #[clap(parse(from_os_str), required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Print the report as JSON
+    #[clap(long)]
+    pub json: bool,
+
+    /// Size in bytes above which ancillary chunks are reported
+    #[clap(long, default_value = "65536")]
+    pub max_ancillary_size: usize,
+}
+
+
+#[derive(Args)]
+pub struct AnalyzeArgs
+{
+    /// Files, directories, glob patterns or - for stdin to analyze
+    #[clap(parse(from_os_str), required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Print the report as JSON
+    #[clap(long)]
+    pub json: bool,
+}
+
+
+#[derive(Args)]
+pub struct ApngArgs
+{
+    #[clap(subcommand)]
+    pub command: ApngCommand,
+}
+
+
+#[derive(Subcommand)]
+pub enum ApngCommand
+{
+    /// Write each frame of an animated PNG as a standalone PNG
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Split(ApngSplitArgs),
+
+    /// Assemble PNG frames into an animated PNG
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Build(ApngBuildArgs),
+}
+
+
+#[derive(Args)]
+pub struct ApngSplitArgs
+{
+    /// Animated PNG file to split, - for stdin
+    #[clap(parse(from_os_str), short='i', long)]
+    pub file_path: PathBuf,
+
+    /// Directory to write the frames to
+    #[clap(parse(from_os_str), short, long, default_value = ".")]
+    pub out_dir: PathBuf,
+}
+
+
+#[derive(Args)]
+pub struct ApngBuildArgs
+{
+    /// PNG files to use as frames, in order
+    #[clap(parse(from_os_str), required = true)]
+    pub frames: Vec<PathBuf>,
+
+    /// Output file of the animated PNG, - for stdout
+    #[clap(parse(from_os_str), short, long)]
+    pub out_file: PathBuf,
+
+    /// Frame delays in milliseconds, either one for all frames or one per frame
+    #[clap(short, long, use_delimiter = true, default_value = "100")]
+    pub delay: Vec<u16>,
+
+    /// Number of times to play the animation, 0 to loop forever
+    #[clap(short, long, default_value = "0")]
+    pub plays: u32,
+}
@@ -0,0 +1,147 @@
+use anyhow::{Context, Result, bail};
+use filetime::FileTime;
+use tempfile::NamedTempFile;
+
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, prelude::*};
+use std::path::{Path, PathBuf};
+
+
+/// The path of the backup of `path`, with `suffix` appended to the file name
+pub fn backup_path(path: &Path, suffix: &str) -> PathBuf
+{
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+
+/// Replaces the contents of `path` atomically: the bytes are written to a temporary file in the
+/// same directory, synced to disk and renamed over the original. A symlink is followed, so that the
+/// file it points to is replaced. Permissions and modification time of the original are kept.
+/// With a backup suffix, the original is kept next to it. Fails if that backup already exists
+pub fn replace(path: &Path, bytes: &[u8], backup_suffix: Option<&str>) -> Result<()>
+{
+    let target = fs::canonicalize(path).with_context(|| format!("Failed to resolve {}", path.display()))?;
+    let metadata = fs::metadata(&target)?;
+    if !metadata.is_file()
+    {
+        bail!("Not a regular file: {}", path.display());
+    }
+
+    let dir = target.parent().with_context(|| format!("Not a file: {}", path.display()))?;
+
+    if let Some(suffix) = backup_suffix
+    {
+        let backup = backup_path(path, suffix);
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&backup)
+            .with_context(|| format!("Failed to create backup {}, it may already exist", backup.display()))?;
+        io::copy(&mut File::open(&target)?, &mut file)?;
+        file.set_permissions(metadata.permissions())?;
+        file.sync_all()?;
+        filetime::set_file_mtime(&backup, FileTime::from_last_modification_time(&metadata))?;
+    }
+
+    let mut temp = NamedTempFile::new_in(dir)?;
+    temp.write_all(bytes)?;
+    temp.as_file().set_permissions(metadata.permissions())?;
+    temp.as_file().sync_all()?;
+    filetime::set_file_mtime(temp.path(), FileTime::from_last_modification_time(&metadata))?;
+
+    temp.persist(&target)?;
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pngme-atomic-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("image.png");
+        fs::write(&path, b"original").unwrap();
+        filetime::set_file_mtime(&path, FileTime::from_unix_time(1_000_000, 0)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_replace() {
+        let path = testing_file("replace");
+        replace(&path, b"modified", None).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"modified");
+        let mtime = FileTime::from_last_modification_time(&fs::metadata(&path).unwrap());
+        assert_eq!(mtime.unix_seconds(), 1_000_000);
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_replace_with_backup() {
+        let path = testing_file("backup");
+        replace(&path, b"modified", Some(".bak")).unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"modified");
+        assert_eq!(fs::read(backup_path(&path, ".bak")).unwrap(), b"original");
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_replace_keeps_existing_backup() {
+        let path = testing_file("existing-backup");
+        replace(&path, b"modified", Some(".bak")).unwrap();
+        assert!(replace(&path, b"modified again", Some(".bak")).is_err());
+
+        assert_eq!(fs::read(&path).unwrap(), b"modified");
+        assert_eq!(fs::read(backup_path(&path, ".bak")).unwrap(), b"original");
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_symlink_target() {
+        let path = testing_file("symlink");
+        let link = path.with_file_name("link.png");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+
+        replace(&link, b"modified", Some(".bak")).unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read(&path).unwrap(), b"modified");
+        assert_eq!(fs::read(backup_path(&link, ".bak")).unwrap(), b"original");
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = testing_file("permissions");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        replace(&path, b"modified", None).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_backup_path() {
+        assert_eq!(backup_path(Path::new("dir/a.png"), ".bak"), PathBuf::from("dir/a.png.bak"));
+    }
+}
@@ -0,0 +1,290 @@
+use crate::stdio;
+
+use anyhow::{Result, bail};
+use rayon::prelude::*;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+
+/// A file to process, along with its path relative to the directory or glob it was found through
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Input
+{
+    pub path: PathBuf,
+    pub relative: PathBuf,
+}
+
+
+impl Input
+{
+    /// The path of the output file in `output_dir`, mirroring the input tree
+    pub fn output_path(&self, output_dir: &Path) -> PathBuf
+    {
+        output_dir.join(&self.relative)
+    }
+}
+
+
+fn is_glob(path: &Path) -> bool
+{
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+
+fn is_png(path: &Path) -> bool
+{
+    path.extension()
+        .map(|e| e.eq_ignore_ascii_case("png"))
+        .unwrap_or(false)
+}
+
+
+/// The leading directory of a glob pattern that contains no wildcards
+fn glob_base(pattern: &Path) -> PathBuf
+{
+    pattern.components()
+        .take_while(|c| !is_glob(Path::new(c.as_os_str())))
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()>
+{
+    for entry in fs::read_dir(dir)?
+    {
+        let entry = entry?;
+        let path = entry.path();
+
+        // Symlinked directories are not followed, so that a link up the tree cannot loop
+        if entry.file_type()?.is_dir()
+        {
+            walk(&path, files)?;
+        }
+        else if is_png(&path) && !path.is_dir()
+        {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Expands files, directories (recursively, `.png` files only, without following symlinked directories),
+/// glob patterns and `-` for stdin into inputs
+pub fn collect(patterns: &[PathBuf]) -> Result<Vec<Input>>
+{
+    let mut inputs = vec![];
+
+    for pattern in patterns
+    {
+        if stdio::is_stdio(pattern)
+        {
+            inputs.push(Input {path: pattern.clone(), relative: PathBuf::from("stdin.png")});
+        }
+        else if pattern.is_dir()
+        {
+            let mut files = vec![];
+            walk(pattern, &mut files)?;
+            files.sort();
+
+            for path in files
+            {
+                let relative = path.strip_prefix(pattern)?.to_path_buf();
+                inputs.push(Input {path, relative});
+            }
+        }
+        else if is_glob(pattern)
+        {
+            let base = glob_base(pattern);
+            let mut matched = 0;
+
+            for path in glob::glob(&pattern.to_string_lossy())?
+            {
+                let path = path?;
+                if path.is_dir()
+                {
+                    continue;
+                }
+
+                let relative = path.strip_prefix(&base).unwrap_or(&path).to_path_buf();
+                inputs.push(Input {path, relative});
+                matched += 1;
+            }
+
+            if matched == 0
+            {
+                bail!("No files match {}", pattern.display());
+            }
+        }
+        else
+        {
+            let relative = match pattern.file_name()
+            {
+                Some(name) => PathBuf::from(name),
+                None => bail!("Not a file: {}", pattern.display()),
+            };
+            inputs.push(Input {path: pattern.clone(), relative});
+        }
+    }
+
+    Ok(inputs)
+}
+
+
+/// Fails if two inputs would be written to the same path in an output directory, as files given by
+/// name are placed there under their name only
+pub fn check_unique_outputs(inputs: &[Input]) -> Result<()>
+{
+    let mut seen = HashMap::new();
+    for input in inputs
+    {
+        if let Some(other) = seen.insert(&input.relative, &input.path)
+        {
+            bail!("{} and {} would both be saved as {}", other.display(), input.path.display(), input.relative.display());
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Runs `task` on every input in parallel and prints its output.
+/// A single input behaves like a plain command, multiple inputs get per-file headers and a summary
+pub fn run<F>(inputs: &[Input], task: F) -> Result<()>
+    where F: Fn(&Input) -> Result<String> + Sync
+{
+    if let [input] = inputs
+    {
+        let output = task(input)?;
+        if !output.is_empty()
+        {
+            println!("{output}");
+        }
+        return Ok(());
+    }
+
+    let results: Vec<Result<String>> = inputs
+        .par_iter()
+        .map(&task)
+        .collect();
+
+    let mut failed = 0;
+    for (input, result) in inputs.iter().zip(results)
+    {
+        match result
+        {
+            Ok(output) => {
+                println!("==> {} <==", input.path.display());
+                if !output.is_empty()
+                {
+                    println!("{output}");
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {e}", input.path.display());
+                failed += 1;
+            }
+        }
+    }
+
+    eprintln!("{} files processed, {} succeeded, {failed} failed", inputs.len(), inputs.len() - failed);
+
+    if failed > 0
+    {
+        bail!("{failed} of {} files failed", inputs.len());
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pngme-batch-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        for file in ["a.png", "b.txt", "sub/c.PNG"] {
+            fs::write(dir.join(file), []).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_collect_directory() {
+        let dir = testing_dir("dir");
+        let inputs = collect(std::slice::from_ref(&dir)).unwrap();
+        let relative: Vec<PathBuf> = inputs.iter().map(|i| i.relative.clone()).collect();
+        assert_eq!(relative, vec![PathBuf::from("a.png"), PathBuf::from("sub/c.PNG")]);
+        assert_eq!(inputs[1].output_path(Path::new("out")), PathBuf::from("out/sub/c.PNG"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_symlink_loop() {
+        let dir = testing_dir("symlink");
+        std::os::unix::fs::symlink(&dir, dir.join("sub").join("loop")).unwrap();
+        std::os::unix::fs::symlink(dir.join("a.png"), dir.join("sub").join("d.png")).unwrap();
+
+        let inputs = collect(std::slice::from_ref(&dir)).unwrap();
+        let relative: Vec<PathBuf> = inputs.iter().map(|i| i.relative.clone()).collect();
+        assert_eq!(relative, vec![PathBuf::from("a.png"), PathBuf::from("sub/c.PNG"), PathBuf::from("sub/d.png")]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_collect_glob() {
+        let dir = testing_dir("glob");
+        let inputs = collect(&[dir.join("**").join("*.txt")]).unwrap();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].relative, PathBuf::from("b.txt"));
+
+        assert!(collect(&[dir.join("*.gif")]).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_collect_file() {
+        let inputs = collect(&[PathBuf::from("some/dir/file.png")]).unwrap();
+        assert_eq!(inputs[0].relative, PathBuf::from("file.png"));
+    }
+
+    #[test]
+    fn test_check_unique_outputs() {
+        let inputs = collect(&[PathBuf::from("a/x.png"), PathBuf::from("b/y.png")]).unwrap();
+        assert!(check_unique_outputs(&inputs).is_ok());
+
+        let inputs = collect(&[PathBuf::from("a/x.png"), PathBuf::from("b/x.png")]).unwrap();
+        assert!(check_unique_outputs(&inputs).is_err());
+    }
+
+    #[test]
+    fn test_collect_stdin() {
+        let inputs = collect(&[PathBuf::from("-")]).unwrap();
+        assert_eq!(inputs[0].path, PathBuf::from("-"));
+        assert_eq!(inputs[0].relative, PathBuf::from("stdin.png"));
+    }
+
+    #[test]
+    fn test_glob_base() {
+        assert_eq!(glob_base(Path::new("./assets/**/*.png")), PathBuf::from("assets"));
+        assert_eq!(glob_base(Path::new("*.png")), PathBuf::new());
+    }
+
+    #[test]
+    fn test_run_reports_failures() {
+        let inputs = collect(&[PathBuf::from("a.png"), PathBuf::from("b.png")]).unwrap();
+        let result = run(&inputs, |input| match input.relative.to_str() {
+            Some("a.png") => Ok(String::new()),
+            _ => bail!("failed"),
+        });
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,740 @@
+use crate::args::{AnalyzeArgs, ApngBuildArgs, ApngSplitArgs, CheckArgs, DecodeArgs, DetectArgs, EncodeArgs, InPlaceArgs,
+    KeygenArgs, PrintArgs, RemoveArgs, RepairCrcArgs, ScanArgs, SealArgs, SignArgs, VerifyArgs, WatermarkArgs,
+    WatermarkKeyArgs};
+use crate::atomic;
+use crate::batch::{self, Input};
+use crate::key::{self, Confirm};
+use crate::stdio;
+
+use pngme::analysis::{self, Analysis};
+use pngme::apng;
+use pngme::camouflage::{self, Carrier};
+use pngme::chunk::Chunk;
+use pngme::chunk_type::ChunkType;
+use pngme::encdec;
+use pngme::manifest::{self, Change};
+use pngme::pixels;
+use pngme::png::Png;
+use pngme::query;
+use pngme::recipients::{self, Identity};
+use pngme::scan::{self, Finding, ScanOptions, Status};
+use pngme::signature;
+use pngme::watermark::{self, WatermarkOptions};
+
+use anyhow::{Context, Result, bail};
+use rayon::prelude::*;
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+
+/// Reads and parses the PNG file of a batch input
+fn read_png(input: &Input) -> Result<Png>
+{
+    let content = stdio::read(&input.path)?;
+    Ok(Png::try_from(&content[..])?)
+}
+
+
+/// Reads the PNG of a batch input, keeping chunks with a wrong CRC
+fn read_png_unchecked(input: &Input) -> Result<Png>
+{
+    let content = stdio::read(&input.path)?;
+    Ok(Png::try_from_unchecked(&content[..])?)
+}
+
+
+/// Gives the bytes of a batch input without copying them. Files are memory mapped where they can be
+/// locked, so that only the pages a query touches are read from disk
+fn with_input_bytes<T, F>(input: &Input, f: F) -> Result<T>
+    where F: FnOnce(&[u8]) -> Result<T>
+{
+    if stdio::is_stdio(&input.path)
+    {
+        let content = stdio::read(&input.path)?;
+        return f(&content);
+    }
+
+    let map = stdio::map_file(&input.path)?;
+    f(&map)
+}
+
+
+/// Collects the inputs, refusing options naming a single output file for several inputs
+fn collect_inputs(file_paths: &[PathBuf], single_outputs: &[(&str, &Option<PathBuf>)]) -> Result<Vec<Input>>
+{
+    let inputs = batch::collect(file_paths)?;
+    if inputs.len() > 1
+    {
+        if let Some((flag, _)) = single_outputs.iter().find(|(_, path)| path.is_some())
+        {
+            bail!("{flag} needs a single input file, found {} files", inputs.len());
+        }
+    }
+
+    Ok(inputs)
+}
+
+
+/// Fails if more than one of the key files and inputs is stdin
+fn check_stdin(key_files: &[&Option<PathBuf>], inputs: &[Input]) -> Result<()>
+{
+    let keys = key_files.iter().filter(|path| path.as_deref().is_some_and(stdio::is_stdio)).count();
+    let inputs = inputs.iter().filter(|input| stdio::is_stdio(&input.path)).count();
+    if keys > 0 && keys + inputs > 1
+    {
+        bail!("Only one key file or input file can be read from stdin");
+    }
+
+    Ok(())
+}
+
+
+/// Encrypts the message of `encode` for its recipients, with its key or in the deniable format
+fn encrypt(args: &EncodeArgs, inputs: &[Input]) -> Result<Vec<u8>>
+{
+    if !args.recipients.is_empty()
+    {
+        let recipients = args.recipients.iter()
+            .map(|recipient| recipients::parse_recipient(recipient))
+            .collect::<Result<Vec<_>>>()?;
+        return recipients::encrypt(&recipients, args.message.as_bytes());
+    }
+
+    check_stdin(&[&args.key.key_file, &args.decoy.decoy_key_file], inputs)?;
+    let key = key::resolve(&args.encryption_key, &args.key, Confirm::Yes)?;
+
+    let deniable = args.decoy.deniable || args.decoy.decoy_message.is_some();
+    let data = match (key, deniable)
+    {
+        (None, false) if args.camouflage => bail!("--camouflage needs an encryption key or a recipient, \
+            as the disguised message could be read by anyone otherwise"),
+        (None, false) => args.message.clone(),
+        (None, true) => bail!("The deniable format needs an encryption key"),
+        (Some(key), false) => encdec::encrypt_message(&key, &args.message),
+        (Some(key), true) => {
+            let decoy_key = key::resolve_decoy(&args.decoy, Confirm::Yes)?;
+            let decoy = match (&decoy_key, &args.decoy.decoy_message)
+            {
+                (Some(decoy_key), Some(message)) => Some((decoy_key.as_str(), message.as_str())),
+                (None, Some(_)) => bail!("A decoy message needs a decoy key"),
+                _ => None,
+            };
+            encdec::encrypt_deniable(&key, &args.message, decoy)?
+        }
+    };
+
+    Ok(data.into_bytes())
+}
+
+
+/// Reads the identities of all identity files
+fn read_identities(paths: &[PathBuf]) -> Result<Vec<Identity>>
+{
+    let mut identities = vec![];
+    for path in paths
+    {
+        let contents = Zeroizing::new(fs::read_to_string(path)
+            .with_context(|| format!("Failed to read identity file {}", path.display()))?);
+        identities.extend(recipients::parse_identities(&contents)
+            .with_context(|| format!("Invalid identity file {}", path.display()))?);
+    }
+
+    Ok(identities)
+}
+
+
+/// Saves the data appended after IEND to a file if asked to, then drops it if asked to
+fn handle_trailer(png: &mut Png, strip: bool, save: &Option<PathBuf>) -> Result<()>
+{
+    if let Some(path) = save
+    {
+        if png.trailer().is_empty()
+        {
+            bail!("No data after IEND to save");
+        }
+        stdio::write(path, png.trailer())?;
+    }
+
+    if strip
+    {
+        png.take_trailer();
+    }
+
+    Ok(())
+}
+
+
+/// Where a modified PNG is saved to
+struct Destination<'a>
+{
+    out_file: &'a Option<PathBuf>,
+    output_dir: &'a Option<PathBuf>,
+    in_place: &'a InPlaceArgs,
+    single: bool,
+}
+
+
+impl<'a> Destination<'a>
+{
+    /// Fails before anything is processed if two inputs would be saved to the same file in the output directory
+    fn new(out_file: &'a Option<PathBuf>, output_dir: &'a Option<PathBuf>, in_place: &'a InPlaceArgs, inputs: &[Input])
+        -> Result<Self>
+    {
+        if output_dir.is_some()
+        {
+            batch::check_unique_outputs(inputs)?;
+        }
+
+        Ok(Self {out_file, output_dir, in_place, single: inputs.len() == 1})
+    }
+}
+
+
+/// Saves the PNG over the input file, to the output file or to the mirrored path in the output
+/// directory. Without any, a single PNG is written to stdout if it is piped,
+/// otherwise the chunk listing is returned instead
+fn save(png: &Png, input: &Input, destination: &Destination) -> Result<String>
+{
+    if destination.in_place.in_place
+    {
+        if stdio::is_stdio(&input.path)
+        {
+            bail!("Cannot modify stdin in place");
+        }
+
+        atomic::replace(&input.path, &png.as_bytes(), destination.in_place.backup_suffix.as_deref())?;
+        return Ok(String::new());
+    }
+
+    let out_file = match (destination.out_file, destination.output_dir) {
+        (Some(out_file), _) => out_file.clone(),
+        (None, Some(output_dir)) => input.output_path(output_dir),
+        (None, None) if destination.single && !stdio::stdout_is_terminal() => PathBuf::from(stdio::STDIO),
+        (None, None) => return Ok(png.to_string()),
+    };
+
+    if let Some(parent) = out_file.parent().filter(|_| !stdio::is_stdio(&out_file))
+    {
+        fs::create_dir_all(parent)?;
+    }
+    stdio::write(&out_file, &png.as_bytes())?;
+
+    Ok(String::new())
+}
+
+
+/// Encodes a message into PNG files and saves the results
+pub fn encode(args: &EncodeArgs) -> Result<()>
+{
+    let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+    let carrier = match args.camouflage
+    {
+        true => Some(Carrier::from_str(&args.chunk_type)?),
+        false => None,
+    };
+
+    if apng::is_animation_chunk(&args.chunk_type)
+    {
+        bail!("Chunk type {chunk_type} is reserved for animation control");
+    }
+
+    if !chunk_type.is_valid()
+    {
+        bail!("Invalid chunk type!");
+    }
+
+    let inputs = collect_inputs(&args.file_paths, &[
+        ("--out-file", &args.out_file),
+        ("--save-trailer", &args.trailer.save_trailer),
+    ])?;
+
+    let data = encrypt(args, &inputs)?;
+    let destination = Destination::new(&args.out_file, &args.output_dir, &args.in_place, &inputs)?;
+
+    batch::run(&inputs, |input| {
+        let mut png = read_png(input)?;
+        handle_trailer(&mut png, args.trailer.strip_trailer, &args.trailer.save_trailer)?;
+
+        match carrier {
+            Some(carrier) => camouflage::hide(&mut png, carrier, &data)?,
+            None => png.append_chunk(Chunk::new(chunk_type.clone(), data.clone())),
+        }
+        png.validate_animation()?;
+
+        save(&png, input, &destination)
+    })
+}
+
+
+/// Searches for a message hidden in PNG files and prints the message if one is found
+pub fn decode(args: &DecodeArgs) -> Result<()>
+{
+    let inputs = batch::collect(&args.file_paths)?;
+    check_stdin(&[&args.key.key_file], &inputs)?;
+    let key = key::resolve(&args.decryption_key, &args.key, Confirm::No)?;
+    let identities = read_identities(&args.identities)?;
+
+    let carrier = match args.camouflage
+    {
+        true => Some(Carrier::from_str(&args.chunk_type)?),
+        false => None,
+    };
+
+    batch::run(&inputs, |input| with_input_bytes(input, |bytes| {
+        let data = match carrier {
+            Some(carrier) => camouflage::reveal(&Png::try_from(bytes)?, carrier)?,
+            None => match query::find_chunk(&mut Cursor::new(bytes), &args.chunk_type)? {
+                Some(chunk) => chunk.data().to_vec(),
+                None => bail!("Chunk type not found."),
+            },
+        };
+
+        let message = if !identities.is_empty() {
+            let message = recipients::decrypt(&identities, &data)?;
+            String::from_utf8(message.to_vec()).context("The decrypted message is not valid UTF-8")?
+        } else {
+            let data = String::from_utf8(data).context("The message is not valid UTF-8")?;
+            match &key {
+                Some(key) => encdec::decrypt_message(key, &data)?,
+                None => data,
+            }
+        };
+
+        Ok(format!("The secret message is: {message}"))
+    }))
+}
+
+
+/// Removes a chunk from PNG files and saves the results
+pub fn remove(args: &RemoveArgs) -> Result<()>
+{
+    if apng::is_animation_chunk(&args.chunk_type)
+    {
+        bail!("Removing {} would break the animation sequence", args.chunk_type);
+    }
+
+    let inputs = collect_inputs(&args.file_paths, &[
+        ("--out-file", &args.out_file),
+        ("--save-trailer", &args.trailer.save_trailer),
+    ])?;
+    let destination = Destination::new(&args.out_file, &args.output_dir, &args.in_place, &inputs)?;
+
+    batch::run(&inputs, |input| {
+        let mut png = read_png(input)?;
+        handle_trailer(&mut png, args.trailer.strip_trailer, &args.trailer.save_trailer)?;
+
+        png.remove_chunk(&args.chunk_type)?;
+        png.validate_animation()?;
+
+        save(&png, input, &destination)
+    })
+}
+
+
+/// Prints all of the chunks in PNG files
+pub fn print_chunks(args: &PrintArgs) -> Result<()>
+{
+    let inputs = collect_inputs(&args.file_paths, &[("--save-trailer", &args.save_trailer)])?;
+
+    batch::run(&inputs, |input| {
+        if args.header
+        {
+            return Ok(query::read_header(&mut stdio::open(&input.path)?)?.to_string());
+        }
+
+        let mut png = read_png_unchecked(input)?;
+        handle_trailer(&mut png, false, &args.save_trailer)?;
+
+        Ok(png.to_string())
+    })
+}
+
+
+/// Rewrites wrong chunk CRCs in PNG files and saves the results
+pub fn repair_crc(args: &RepairCrcArgs) -> Result<()>
+{
+    let inputs = collect_inputs(&args.file_paths, &[("--out-file", &args.out_file)])?;
+    let destination = Destination::new(&args.out_file, &args.output_dir, &args.in_place, &inputs)?;
+
+    batch::run(&inputs, |input| {
+        let mut png = read_png_unchecked(input)?;
+
+        let repaired = png.repair_crcs();
+        for (index, stored_crc, crc) in &repaired
+        {
+            let chunk_type = png.chunks()[*index].chunk_type();
+            eprintln!("{}: repaired {chunk_type} chunk {index}: stored {stored_crc:#010x}, expected {crc:#010x}",
+                input.path.display());
+        }
+
+        if repaired.is_empty() && args.in_place.in_place
+        {
+            return Ok(String::new());
+        }
+
+        save(&png, input, &destination)
+    })
+}
+
+
+/// Scan result of a single file
+#[derive(Serialize)]
+struct ScanReport
+{
+    path: PathBuf,
+    findings: Vec<Finding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+
+/// Collects the inputs of a command that reports a `Status`, printing the error if they cannot be
+/// collected. That counts as a failed file rather than an error exit, whose code reads as suspicious
+fn collect_reported(paths: &[PathBuf]) -> Option<Vec<Input>>
+{
+    match batch::collect(paths)
+    {
+        Ok(inputs) => Some(inputs),
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            None
+        }
+    }
+}
+
+
+/// Scans PNG files for hidden or smuggled data and prints a report.
+/// Returns `Suspicious` if anything was found and `Failed` if a file could not be scanned
+pub fn scan(args: &ScanArgs) -> Result<Status>
+{
+    let options = ScanOptions {max_ancillary_size: args.max_ancillary_size};
+    let inputs = match collect_reported(&args.paths)
+    {
+        Some(inputs) => inputs,
+        None => return Ok(Status::Failed),
+    };
+
+    let reports: Vec<ScanReport> = inputs
+        .par_iter()
+        .map(|input| {
+            let result = stdio::read(&input.path)
+                .and_then(|content| Ok(scan::scan(&content, &options)?));
+
+            match result
+            {
+                Ok(findings) => ScanReport {path: input.path.clone(), findings, error: None},
+                Err(e) => ScanReport {path: input.path.clone(), findings: vec![], error: Some(e.to_string())},
+            }
+        })
+        .collect();
+
+    let status = reports
+        .iter()
+        .map(|r| if r.error.is_some() { Status::Failed } else { Status::of(&r.findings) })
+        .max()
+        .unwrap_or(Status::Clean);
+
+    if args.json
+    {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(status);
+    }
+
+    for report in &reports
+    {
+        match (&report.error, report.findings.is_empty())
+        {
+            (Some(error), _) => println!("{}: error: {error}", report.path.display()),
+            (None, true) => println!("{}: clean", report.path.display()),
+            (None, false) => {
+                println!("{}:", report.path.display());
+                for finding in &report.findings
+                {
+                    println!("  {finding}");
+                }
+            }
+        }
+    }
+
+    Ok(status)
+}
+
+
+/// Analysis of a single file
+#[derive(Serialize)]
+struct AnalysisReport
+{
+    path: PathBuf,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    analysis: Option<Analysis>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+
+/// Runs steganalysis on PNG files and prints a report per file.
+/// Returns `Suspicious` if any file looks like it hides data and `Failed` if a file could not be analyzed
+pub fn analyze(args: &AnalyzeArgs) -> Result<Status>
+{
+    let inputs = match collect_reported(&args.paths)
+    {
+        Some(inputs) => inputs,
+        None => return Ok(Status::Failed),
+    };
+
+    let reports: Vec<AnalysisReport> = inputs
+        .par_iter()
+        .map(|input| {
+            let result = stdio::read(&input.path)
+                .and_then(|content| analysis::analyze(&Png::try_from_unchecked(&content)?));
+
+            match result
+            {
+                Ok(analysis) => AnalysisReport {path: input.path.clone(), analysis: Some(analysis), error: None},
+                Err(e) => AnalysisReport {path: input.path.clone(), analysis: None, error: Some(e.to_string())},
+            }
+        })
+        .collect();
+
+    let status = reports
+        .iter()
+        .map(|r| match &r.analysis {
+            Some(analysis) if analysis.is_suspicious() => Status::Suspicious,
+            Some(_) => Status::Clean,
+            None => Status::Failed,
+        })
+        .max()
+        .unwrap_or(Status::Clean);
+
+    if args.json
+    {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(status);
+    }
+
+    for report in &reports
+    {
+        match (&report.error, &report.analysis)
+        {
+            (Some(error), _) => println!("{}: error: {error}", report.path.display()),
+            (None, Some(analysis)) => println!("{}:\n{analysis}\n", report.path.display()),
+            (None, None) => {}
+        }
+    }
+
+    Ok(status)
+}
+
+
+/// Writes every composited frame of an animated PNG to a separate PNG file
+pub fn apng_split(args: &ApngSplitArgs) -> Result<()>
+{
+    let content = stdio::read(&args.file_path)?;
+    let png = Png::try_from(&content[..])?;
+
+    if !png.is_animated()
+    {
+        bail!("Not an animated PNG.");
+    }
+
+    // Frames of stdin would start with -, which later commands take for an option
+    let stem = match args.file_path.file_stem()
+    {
+        Some(stem) if !stdio::is_stdio(&args.file_path) => stem.to_string_lossy().into_owned(),
+        _ => String::from("frame"),
+    };
+
+    fs::create_dir_all(&args.out_dir)?;
+
+    for (index, (image, control)) in apng::render(&png)?.iter().enumerate()
+    {
+        let out_file = args.out_dir.join(format!("{stem}_{index:03}.png"));
+        fs::write(&out_file, image.to_png()?.as_bytes())?;
+
+        println!("{} ({:.3} s)", out_file.display(), control.delay());
+    }
+
+    Ok(())
+}
+
+
+/// Assembles PNG files into an animated PNG
+pub fn apng_build(args: &ApngBuildArgs) -> Result<()>
+{
+    let mut images = vec![];
+    for path in &args.frames
+    {
+        let content = stdio::read(path)?;
+        images.push(pixels::decode(&Png::try_from(&content[..])?)?);
+    }
+
+    let png = apng::build(&images, &args.delay, args.plays)?;
+    stdio::write(&args.out_file, &png.as_bytes())?;
+
+    Ok(())
+}
+
+
+/// Generates an age identity or a signing key and writes it to a new file, or prints it
+pub fn keygen(args: &KeygenArgs) -> Result<()>
+{
+    let (key_file, public_key) = if args.signing {
+        let (key_file, public_key) = signature::generate_key()?;
+        (key_file, signature::public_key_string(&public_key))
+    } else {
+        let (identity, recipient) = recipients::generate_identity();
+        (identity, recipient.to_string())
+    };
+
+    match &args.out_file
+    {
+        Some(path) if !stdio::is_stdio(path) => {
+            stdio::write_secret(path, key_file.as_bytes())?;
+            eprintln!("Public key: {public_key}");
+        }
+        _ => print!("{}", *key_file),
+    }
+
+    Ok(())
+}
+
+
+/// Signs the chunks of PNG files and saves the results
+pub fn sign(args: &SignArgs) -> Result<()>
+{
+    let contents = Zeroizing::new(fs::read_to_string(&args.key)
+        .with_context(|| format!("Failed to read key file {}", args.key.display()))?);
+    let key = signature::parse_signing_key(&contents)?;
+
+    let inputs = collect_inputs(&args.file_paths, &[("--out-file", &args.out_file)])?;
+    let destination = Destination::new(&args.out_file, &args.output_dir, &args.in_place, &inputs)?;
+
+    batch::run(&inputs, |input| {
+        let mut png = read_png(input)?;
+        signature::sign(&mut png, &key)?;
+
+        save(&png, input, &destination)
+    })
+}
+
+
+/// Checks the signatures of PNG files. Fails for files whose chunks changed since signing
+pub fn verify(args: &VerifyArgs) -> Result<()>
+{
+    let keys = args.public_keys.iter()
+        .map(|key| signature::parse_verifying_key(key))
+        .collect::<Result<Vec<_>>>()?;
+    let inputs = batch::collect(&args.file_paths)?;
+
+    batch::run(&inputs, |input| {
+        let png = read_png_unchecked(input)?;
+        let verification = signature::verify(&png, &keys)?;
+        let key_id = signature::key_id_string(&verification.key_id);
+
+        if verification.changes.is_empty()
+        {
+            return Ok(format!("Valid signature by key {key_id}, no chunks changed since signing"));
+        }
+
+        bail!("Valid signature by key {key_id}, but chunks changed since signing:\n{}", list_changes(&verification.changes))
+    })
+}
+
+
+/// One indented line per change
+fn list_changes(changes: &[Change]) -> String
+{
+    changes.iter().map(|change| format!("  {change}")).collect::<Vec<_>>().join("\n")
+}
+
+
+/// Seals the chunks of PNG files and saves the results
+pub fn seal(args: &SealArgs) -> Result<()>
+{
+    let inputs = collect_inputs(&args.file_paths, &[("--out-file", &args.out_file)])?;
+    let destination = Destination::new(&args.out_file, &args.output_dir, &args.in_place, &inputs)?;
+
+    batch::run(&inputs, |input| {
+        let mut png = read_png(input)?;
+        manifest::seal(&mut png)?;
+
+        save(&png, input, &destination)
+    })
+}
+
+
+/// Checks PNG files against their seal. Fails for files whose chunks changed since sealing
+pub fn check(args: &CheckArgs) -> Result<()>
+{
+    let inputs = batch::collect(&args.file_paths)?;
+
+    batch::run(&inputs, |input| {
+        let png = read_png_unchecked(input)?;
+        let changes = manifest::check(&png)?;
+
+        if changes.is_empty()
+        {
+            return Ok("No chunks changed since sealing".to_string());
+        }
+
+        bail!("Chunks changed since sealing:\n{}", list_changes(&changes))
+    })
+}
+
+
+/// Options with the watermark key, which is required as anyone knowing it can read or remove the mark
+fn watermark_options(args: &WatermarkKeyArgs, inputs: &[Input], confirm: Confirm) -> Result<WatermarkOptions>
+{
+    check_stdin(&[&args.key.key_file], inputs)?;
+    let key = key::resolve(&args.watermark_key, &args.key, confirm)?
+        .context("Missing watermark key, give --watermark-key, --key-file, --key-env or --ask-key")?;
+
+    Ok(WatermarkOptions::new(key.as_str()))
+}
+
+
+/// Embeds a watermark in PNG files and saves the results
+pub fn watermark(args: &WatermarkArgs) -> Result<()>
+{
+    let inputs = collect_inputs(&args.file_paths, &[("--out-file", &args.out_file)])?;
+    let options = WatermarkOptions {strength: args.strength, ..watermark_options(&args.key, &inputs, Confirm::Yes)?};
+    let destination = Destination::new(&args.out_file, &args.output_dir, &args.in_place, &inputs)?;
+
+    batch::run(&inputs, |input| {
+        let png = watermark::embed_png(&read_png(input)?, args.id, &options)?;
+        save(&png, input, &destination)
+    })
+}
+
+
+/// Looks for watermarks in PNG files and prints the IDs found
+pub fn detect(args: &DetectArgs) -> Result<()>
+{
+    let inputs = batch::collect(&args.file_paths)?;
+    let options = watermark_options(&args.key, &inputs, Confirm::No)?;
+
+    batch::run(&inputs, |input| {
+        Ok(watermark::detect_png(&read_png(input)?, &options)?.to_string())
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_collect_failure() {
+        let paths = vec![std::env::temp_dir().join("pngme-no-such-dir-*").join("*.png")];
+
+        let status = scan(&ScanArgs {paths: paths.clone(), json: false, max_ancillary_size: 65536}).unwrap();
+        assert_eq!(status.exit_code(), 2);
+        let status = analyze(&AnalyzeArgs {paths, json: false}).unwrap();
+        assert_eq!(status.exit_code(), 2);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::args::{DecoyArgs, KeyArgs};
+use crate::stdio;
+
+use anyhow::{Context, Result, bail};
+use zeroize::Zeroizing;
+
+use std::env;
+use std::path::Path;
+
+
+/// A key that is wiped from memory when dropped
+pub type Key = Zeroizing<String>;
+
+
+/// Whether a prompted key must be typed twice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confirm
+{
+    Yes,
+    No,
+}
+
+
+/// The key given on the command line, in a file, in an environment variable or at a prompt, if any
+pub fn resolve(key: &Option<String>, args: &KeyArgs, confirm: Confirm) -> Result<Option<Key>>
+{
+    resolve_from(key.as_ref(), args.key_file.as_deref(), args.key_env.as_deref(), args.ask_key, "key", confirm)
+}
+
+
+/// The decoy key of a deniable message, in a file, in an environment variable or at a prompt, if any
+pub fn resolve_decoy(args: &DecoyArgs, confirm: Confirm) -> Result<Option<Key>>
+{
+    resolve_from(None, args.decoy_key_file.as_deref(), args.decoy_key_env.as_deref(), args.ask_decoy_key,
+        "decoy key", confirm)
+}
+
+
+fn resolve_from(key: Option<&String>, file: Option<&Path>, env: Option<&str>, ask: bool, name: &str, confirm: Confirm)
+    -> Result<Option<Key>>
+{
+    let key = if let Some(key) = key
+    {
+        Key::new(key.clone())
+    }
+    else if let Some(path) = file
+    {
+        from_file(path)?
+    }
+    else if let Some(variable) = env
+    {
+        from_env(variable)?
+    }
+    else if ask
+    {
+        prompt(name, confirm)?
+    }
+    else
+    {
+        return Ok(None);
+    };
+
+    if key.is_empty()
+    {
+        bail!("The {name} is empty");
+    }
+
+    Ok(Some(key))
+}
+
+
+/// Reads a key from a file, or from stdin for `-`, without its final line break
+fn from_file(path: &Path) -> Result<Key>
+{
+    let bytes = Zeroizing::new(stdio::read(path).with_context(|| format!("Failed to read key file {}", path.display()))?);
+
+    let mut key = Key::new(std::str::from_utf8(&bytes).context("The key file is not valid UTF-8")?.to_string());
+    if key.ends_with('\n')
+    {
+        key.pop();
+        if key.ends_with('\r')
+        {
+            key.pop();
+        }
+    }
+
+    Ok(key)
+}
+
+
+fn from_env(name: &str) -> Result<Key>
+{
+    match env::var(name)
+    {
+        Ok(key) => Ok(Key::new(key)),
+        Err(_) => bail!("Environment variable {name} is not set or not valid UTF-8"),
+    }
+}
+
+
+/// Asks for a key on the terminal without echoing it
+fn prompt(name: &str, confirm: Confirm) -> Result<Key>
+{
+    let context = || format!("Failed to prompt for the {name} on the terminal");
+
+    let mut label = format!("{name}: ");
+    label[..1].make_ascii_uppercase();
+    let key = Key::new(rpassword::prompt_password(label).with_context(context)?);
+
+    if confirm == Confirm::Yes
+    {
+        let again = Key::new(rpassword::prompt_password(format!("Confirm {name}: ")).with_context(context)?);
+        if key != again
+        {
+            bail!("The {name}s do not match");
+        }
+    }
+
+    Ok(key)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn key_args() -> KeyArgs {
+        KeyArgs {key_file: None, key_env: None, ask_key: false}
+    }
+
+    #[test]
+    fn test_from_file() {
+        let path = env::temp_dir().join(format!("pngme-key-{}", std::process::id()));
+
+        fs::write(&path, "secret\r\n").unwrap();
+        let args = KeyArgs {key_file: Some(path.clone()), ..key_args()};
+        assert_eq!(resolve(&None, &args, Confirm::Yes).unwrap().unwrap().as_str(), "secret");
+
+        fs::write(&path, "two\nlines\n").unwrap();
+        assert_eq!(from_file(&path).unwrap().as_str(), "two\nlines");
+
+        fs::write(&path, "\n").unwrap();
+        assert!(resolve(&None, &args, Confirm::No).is_err());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_from_env() {
+        let name = format!("PNGME_TEST_KEY_{}", std::process::id());
+        env::set_var(&name, "secret");
+
+        let args = KeyArgs {key_env: Some(name.clone()), ..key_args()};
+        assert_eq!(resolve(&None, &args, Confirm::No).unwrap().unwrap().as_str(), "secret");
+
+        env::remove_var(&name);
+        assert!(resolve(&None, &args, Confirm::No).is_err());
+    }
+
+    #[test]
+    fn test_decoy() {
+        let name = format!("PNGME_TEST_DECOY_{}", std::process::id());
+        env::set_var(&name, "decoy");
+
+        let mut args = DecoyArgs {decoy_message: None, decoy_key_file: None, decoy_key_env: None, ask_decoy_key: false,
+            deniable: false};
+        assert!(resolve_decoy(&args, Confirm::No).unwrap().is_none());
+
+        args.decoy_key_env = Some(name.clone());
+        assert_eq!(resolve_decoy(&args, Confirm::No).unwrap().unwrap().as_str(), "decoy");
+        env::remove_var(&name);
+    }
+
+    #[test]
+    fn test_no_key() {
+        assert!(resolve(&None, &key_args(), Confirm::No).unwrap().is_none());
+        let key = resolve(&Some("secret".to_string()), &key_args(), Confirm::No).unwrap();
+        assert_eq!(key.unwrap().as_str(), "secret");
+    }
+}
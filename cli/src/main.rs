@@ -0,0 +1,85 @@
+mod args;
+mod atomic;
+mod batch;
+mod commands;
+mod key;
+mod stdio;
+
+use args::{ApngCommand, PngMeArgs};
+
+use anyhow::Result;
+use clap::{AppSettings, Parser};
+
+
+/// Encode a super secret message in a PNG!
+#[derive(Parser)]
+#[clap(author, version, about, long_about = None)]
+#[clap(global_setting(AppSettings::PropagateVersion))]
+#[clap(global_setting(AppSettings::UseLongFormatForHelpSubcommand))]
+struct Cli {
+    #[clap(subcommand)]
+    command: PngMeArgs,
+}
+
+
+fn main() -> Result<()>
+{
+    let cli = Cli::parse();
+
+    match &cli.command {
+        PngMeArgs::Encode(args) => {
+            commands::encode(args)?;
+        }
+        PngMeArgs::Decode(args) => {
+            commands::decode(args)?;
+        }
+        PngMeArgs::Remove(args) => {
+            commands::remove(args)?;
+        }
+        PngMeArgs::Print(args) => {
+            commands::print_chunks(args)?;
+        }
+        PngMeArgs::RepairCrc(args) => {
+            commands::repair_crc(args)?;
+        }
+        PngMeArgs::Scan(args) => {
+            let status = commands::scan(args)?;
+            std::process::exit(status.exit_code());
+        }
+        PngMeArgs::Analyze(args) => {
+            let status = commands::analyze(args)?;
+            std::process::exit(status.exit_code());
+        }
+        PngMeArgs::Seal(args) => {
+            commands::seal(args)?;
+        }
+        PngMeArgs::Check(args) => {
+            commands::check(args)?;
+        }
+        PngMeArgs::Sign(args) => {
+            commands::sign(args)?;
+        }
+        PngMeArgs::Verify(args) => {
+            commands::verify(args)?;
+        }
+        PngMeArgs::Watermark(args) => {
+            commands::watermark(args)?;
+        }
+        PngMeArgs::Detect(args) => {
+            commands::detect(args)?;
+        }
+        PngMeArgs::Keygen(args) => {
+            commands::keygen(args)?;
+        }
+        PngMeArgs::Apng(args) => match &args.command {
+            ApngCommand::Split(args) => {
+                commands::apng_split(args)?;
+            }
+            ApngCommand::Build(args) => {
+                commands::apng_build(args)?;
+            }
+        }
+    }
+
+    Ok(())
+}
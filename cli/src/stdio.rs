@@ -0,0 +1,215 @@
+use anyhow::{Context, Result, bail};
+use memmap2::Mmap;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, IsTerminal};
+use std::io::prelude::*;
+use std::ops::Deref;
+use std::path::Path;
+
+
+/// The path standing for stdin when reading and stdout when writing
+pub const STDIO: &str = "-";
+
+
+pub fn is_stdio(path: &Path) -> bool
+{
+    path == Path::new(STDIO)
+}
+
+
+pub fn stdout_is_terminal() -> bool
+{
+    io::stdout().is_terminal()
+}
+
+
+/// Reads a file, or all of stdin for `-`
+pub fn read(path: &Path) -> Result<Vec<u8>>
+{
+    if !is_stdio(path)
+    {
+        return Ok(fs::read(path)?);
+    }
+
+    let mut bytes = vec![];
+    io::stdin().lock().read_to_end(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+
+/// Opens a file for buffered reading, or stdin for `-`
+pub fn open(path: &Path) -> Result<Box<dyn BufRead>>
+{
+    if is_stdio(path)
+    {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+
+    Ok(Box::new(BufReader::new(File::open(path)?)))
+}
+
+
+/// The contents of a file, memory mapped where it could be locked
+pub enum FileBytes
+{
+    /// Mapped under a shared advisory lock, which the file releases when dropped
+    Mapped {map: Mmap, _locked: File},
+    Read(Vec<u8>),
+}
+
+
+impl Deref for FileBytes
+{
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8]
+    {
+        match self
+        {
+            FileBytes::Mapped {map, ..} => map,
+            FileBytes::Read(bytes) => bytes,
+        }
+    }
+}
+
+
+/// Takes a shared advisory lock on `file` without waiting, held until it is closed
+#[cfg(unix)]
+fn lock_shared(file: &File) -> bool
+{
+    use std::os::unix::io::AsRawFd;
+
+    // Safety: flock only operates on the descriptor, which `file` keeps open
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) == 0 }
+}
+
+
+#[cfg(not(unix))]
+fn lock_shared(_: &File) -> bool
+{
+    false
+}
+
+
+/// Memory maps a file for parsing with `PngRef`, under a shared advisory lock that holds off writers
+/// taking an exclusive one. A writer that ignores the lock and truncates the file kills the process
+/// with SIGBUS, so files that cannot be locked, or anywhere without `flock`, are read instead
+pub fn map_file(path: &Path) -> Result<FileBytes>
+{
+    let mut file = File::open(path)?;
+
+    if lock_shared(&file)
+    {
+        // Safety: the map is only read, and it is only sound as long as no other process shrinks the
+        // file, which the lock ensures for writers that lock it. pngme replaces files by renaming
+        let map = unsafe { Mmap::map(&file)? };
+        return Ok(FileBytes::Mapped {map, _locked: file});
+    }
+
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes)?;
+    Ok(FileBytes::Read(bytes))
+}
+
+
+/// Writes a file, or binary data to stdout for `-` unless stdout is a terminal
+pub fn write(path: &Path, bytes: &[u8]) -> Result<()>
+{
+    if !is_stdio(path)
+    {
+        return Ok(fs::write(path, bytes)?);
+    }
+
+    if stdout_is_terminal()
+    {
+        bail!("Refusing to write binary data to a terminal, redirect stdout or use --out-file");
+    }
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(bytes)?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+
+/// Writes secret bytes to a new file that only the owner can read. Fails if the file exists
+pub fn write_secret(path: &Path, bytes: &[u8]) -> Result<()>
+{
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_stdio() {
+        assert!(is_stdio(Path::new("-")));
+        assert!(!is_stdio(Path::new("./-")));
+        assert!(!is_stdio(Path::new("file.png")));
+    }
+
+    #[test]
+    fn test_read_write_file() {
+        let path = std::env::temp_dir().join(format!("pngme-stdio-{}", std::process::id()));
+        write(&path, b"bytes").unwrap();
+        assert_eq!(read(&path).unwrap(), b"bytes");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_write_secret() {
+        let path = std::env::temp_dir().join(format!("pngme-secret-{}", std::process::id()));
+        write_secret(&path, b"secret").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"secret");
+        assert!(write_secret(&path, b"other").is_err());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_map_file() {
+        let path = std::env::temp_dir().join(format!("pngme-map-{}.png", std::process::id()));
+        fs::write(&path, b"mapped bytes").unwrap();
+
+        let map = map_file(&path).unwrap();
+        assert_eq!(&map[..], b"mapped bytes");
+        #[cfg(unix)]
+        assert!(matches!(map, FileBytes::Mapped {..}));
+        drop(map);
+
+        // A file that a writer holds locked is read, not mapped
+        #[cfg(unix)]
+        {
+            use std::os::unix::io::AsRawFd;
+
+            let writer = File::open(&path).unwrap();
+            assert_eq!(unsafe { libc::flock(writer.as_raw_fd(), libc::LOCK_EX) }, 0);
+            let bytes = map_file(&path).unwrap();
+            assert!(matches!(bytes, FileBytes::Read(_)));
+            assert_eq!(&bytes[..], b"mapped bytes");
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+}
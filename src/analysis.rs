@@ -0,0 +1,764 @@
+use crate::chunk::Chunk;
+use crate::png::Png;
+use crate::pixels;
+
+use anyhow::Result;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use flate2::read::{DeflateDecoder, ZlibDecoder};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use std::fmt;
+use std::io::prelude::*;
+
+
+/// Number of growing portions of the samples, from the first pixels on, that the chi-square test is run on
+const CHI_SQUARE_STEPS: usize = 8;
+
+/// Pairs of values seen fewer times than this are left out of the chi-square test
+const CHI_SQUARE_MIN_EXPECTED: f64 = 5.0;
+
+/// Embedding probability of the chi-square test above which the LSBs look randomized
+const CHI_SQUARE_THRESHOLD: f64 = 0.95;
+
+/// Share of LSBs estimated by RS analysis as carrying a message above which the pixels are suspicious.
+/// Clean images usually stay within a few percent
+const RS_THRESHOLD: f64 = 0.1;
+
+/// Share of flat groups above which the image is more likely a drawing than a photo,
+/// for which RS analysis overestimates
+const RS_MAX_FLAT: f64 = 0.25;
+
+/// Pixels per group of RS analysis, and the mask flipped within each group
+const RS_MASK: [bool; 4] = [false, true, true, false];
+
+/// Shortest decoded payload whose entropy says anything
+const MIN_PAYLOAD_LENGTH: usize = 32;
+
+/// Share of the highest entropy possible for its length above which a payload looks encrypted
+const ENCRYPTED_ENTROPY: f64 = 0.9;
+
+/// Probability of uneven byte counts below which a long payload is not random but, say, compressed
+const UNIFORMITY_THRESHOLD: f64 = 1e-4;
+
+/// Most bytes inflated per compressed byte of a chunk, enough to judge its contents without
+/// inflating a decompression bomb in full
+const INFLATE_LIMIT: usize = 16;
+
+/// Start of a message in the age format
+const AGE_HEADER: &[u8] = b"age-encryption.org/v1\n";
+
+
+/// Statistics of the least significant bits of 8-bit grayscale or truecolor pixels
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PixelAnalysis
+{
+    /// Color samples looked at, alpha excluded
+    pub samples: usize,
+    /// Chi-square probability that the LSBs were replaced by message bits, for the first eighth
+    /// of the samples, the first two eighths and so on up to all of them
+    pub chi_square: Vec<f64>,
+    /// Share of the LSBs that RS analysis estimates were changed, from 0 to 1.
+    /// `None` for drawings with large flat areas, where the estimate is unreliable
+    pub rs_estimate: Option<f64>,
+}
+
+
+/// Entropy and content of an ancillary chunk
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ChunkAnalysis
+{
+    pub index: usize,
+    pub chunk_type: String,
+    pub length: usize,
+    /// Shannon entropy of the chunk data in bits per byte
+    pub entropy: f64,
+    /// Why the chunk looks like it holds an encrypted payload, if it does
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub encrypted: Option<String>,
+}
+
+
+/// Steganalysis report of a PNG
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Analysis
+{
+    /// `None` for palette and 16-bit images, whose LSBs the tests do not model
+    pub pixels: Option<PixelAnalysis>,
+    pub chunks: Vec<ChunkAnalysis>,
+}
+
+
+impl PixelAnalysis
+{
+    /// Whether either test points to LSB replacement. Only the chi-square test over all samples counts,
+    /// smooth histograms make it fire on smaller portions of clean photos
+    pub fn is_suspicious(&self) -> bool
+    {
+        self.chi_square.last().is_some_and(|&p| p > CHI_SQUARE_THRESHOLD)
+            || self.rs_estimate.is_some_and(|estimate| estimate > RS_THRESHOLD)
+    }
+}
+
+
+impl Analysis
+{
+    pub fn is_suspicious(&self) -> bool
+    {
+        self.pixels.as_ref().is_some_and(PixelAnalysis::is_suspicious)
+            || self.chunks.iter().any(|chunk| chunk.encrypted.is_some())
+    }
+}
+
+
+/// Runs the pixel tests, if the image type allows, and the entropy analysis of every ancillary chunk
+pub fn analyze(png: &Png) -> Result<Analysis>
+{
+    let header = png.image_header()?;
+    let pixels = match (header.color_type, header.bit_depth)
+    {
+        (0 | 2 | 4 | 6, 8) => {
+            let channels = if header.color_type & 2 == 0 { 1 } else { 3 };
+            Some(analyze_pixels(&pixels::decode(png)?, channels))
+        }
+        _ => None,
+    };
+
+    let chunks = png.iter()
+        .enumerate()
+        .filter(|(_, chunk)| !chunk.chunk_type().is_critical())
+        .map(|(index, chunk)| analyze_chunk(index, chunk))
+        .collect();
+
+    Ok(Analysis {pixels, chunks})
+}
+
+
+/// Runs chi-square and RS analysis on the first `channels` of each RGBA pixel
+pub fn analyze_pixels(image: &pixels::Image, channels: usize) -> PixelAnalysis
+{
+    let samples: Vec<u8> = image.pixels.chunks(4).flat_map(|pixel| pixel[..channels].to_vec()).collect();
+
+    let chi_square = (1..=CHI_SQUARE_STEPS)
+        .map(|step| chi_square(&samples[..samples.len() * step / CHI_SQUARE_STEPS]))
+        .collect();
+
+    let row = image.width as usize * channels;
+    let rs_estimate = if row == 0 { None } else { rs_estimate(&samples, row, channels) };
+
+    PixelAnalysis {samples: samples.len(), chi_square, rs_estimate}
+}
+
+
+/// Probability that the values of each pair 2k, 2k + 1 occur equally often, as they do once
+/// message bits replace the LSBs (Westfeld and Pfitzmann)
+fn chi_square(samples: &[u8]) -> f64
+{
+    let mut histogram = [0usize; 256];
+    for &sample in samples
+    {
+        histogram[sample as usize] += 1;
+    }
+
+    let mut statistic = 0.0;
+    let mut categories = 0;
+    for pair in histogram.chunks(2)
+    {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected >= CHI_SQUARE_MIN_EXPECTED
+        {
+            statistic += (pair[0] as f64 - expected).powi(2) / expected;
+            categories += 1;
+        }
+    }
+
+    if categories < 2
+    {
+        return 0.0;
+    }
+
+    1.0 - regularized_gamma((categories - 1) as f64 / 2.0, statistic / 2.0)
+}
+
+
+/// Counts of regular and singular groups under the mask and the negated mask as shares of all groups,
+/// and the share of flat groups
+fn rs_counts(samples: &[u8], row: usize, channels: usize, flip_all: bool) -> ([f64; 4], f64)
+{
+    let smoothness = |group: &[i16]| -> i16 { group.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum() };
+    let flip = |value: i16| value ^ 1;
+    let flip_negative = |value: i16| ((value + 1) ^ 1) - 1;
+
+    let mut counts = [0usize; 4];
+    let mut groups = 0;
+    let mut flat = 0;
+    let width = row / channels;
+
+    for line in samples.chunks_exact(row)
+    {
+        for channel in 0..channels
+        {
+            for start in (0..width - width % RS_MASK.len()).step_by(RS_MASK.len())
+            {
+                let group: Vec<i16> = (start..start + RS_MASK.len())
+                    .map(|x| line[x * channels + channel] as i16)
+                    .map(|value| if flip_all { flip(value) } else { value })
+                    .collect();
+
+                if group.iter().all(|&value| value == group[0])
+                {
+                    flat += 1;
+                }
+
+                let original = smoothness(&group);
+                for (negative, flipping) in [(false, &flip as &dyn Fn(i16) -> i16), (true, &flip_negative)]
+                {
+                    let flipped: Vec<i16> = group.iter().zip(RS_MASK)
+                        .map(|(&value, masked)| if masked { flipping(value) } else { value })
+                        .collect();
+
+                    let changed = smoothness(&flipped);
+                    let offset = if negative { 2 } else { 0 };
+                    if changed > original
+                    {
+                        counts[offset] += 1;
+                    }
+                    else if changed < original
+                    {
+                        counts[offset + 1] += 1;
+                    }
+                }
+                groups += 1;
+            }
+        }
+    }
+
+    let groups = groups.max(1) as f64;
+    (counts.map(|count| count as f64 / groups), flat as f64 / groups)
+}
+
+
+/// Share of changed LSBs estimated by RS analysis (Fridrich, Goljan and Du)
+fn rs_estimate(samples: &[u8], row: usize, channels: usize) -> Option<f64>
+{
+    let ([r, s, r_negative, s_negative], flat) = rs_counts(samples, row, channels, false);
+    if flat > RS_MAX_FLAT || r + s == 0.0
+    {
+        return None;
+    }
+    let ([r_flipped, s_flipped, r_negative_flipped, s_negative_flipped], _) = rs_counts(samples, row, channels, true);
+
+    let d0 = r - s;
+    let d1 = r_flipped - s_flipped;
+    let d0_negative = r_negative - s_negative;
+    let d1_negative = r_negative_flipped - s_negative_flipped;
+
+    let a = 2.0 * (d1 + d0);
+    let b = d0_negative - d1_negative - d1 - 3.0 * d0;
+    let c = d0 - d0_negative;
+
+    let x = if a.abs() < f64::EPSILON
+    {
+        if b.abs() < f64::EPSILON { 0.0 } else { -c / b }
+    }
+    else
+    {
+        let discriminant = (b * b - 4.0 * a * c).max(0.0).sqrt();
+        let roots = [(-b + discriminant) / (2.0 * a), (-b - discriminant) / (2.0 * a)];
+        if roots[0].abs() < roots[1].abs() { roots[0] } else { roots[1] }
+    };
+
+    let estimate = x / (x - 0.5);
+    Some(if estimate > 0.0 { estimate.min(1.0) } else { 0.0 })
+}
+
+
+/// Regularized lower incomplete gamma function P(a, x), by its series or continued fraction
+fn regularized_gamma(a: f64, x: f64) -> f64
+{
+    if x <= 0.0
+    {
+        return 0.0;
+    }
+
+    let prefix = (a * x.ln() - x - ln_gamma(a)).exp();
+    if x < a + 1.0
+    {
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        while term.abs() > sum.abs() * 1e-15
+        {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+        }
+        return (sum * prefix).min(1.0);
+    }
+
+    // Lentz's method for the continued fraction of Q(a, x)
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut fraction = d;
+    for i in 1..1000
+    {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        d = if d.abs() < tiny { tiny } else { d };
+        c = b + an / c;
+        c = if c.abs() < tiny { tiny } else { c };
+        d = 1.0 / d;
+        let delta = d * c;
+        fraction *= delta;
+        if (delta - 1.0).abs() < 1e-15
+        {
+            break;
+        }
+    }
+
+    (1.0 - prefix * fraction).max(0.0)
+}
+
+
+/// ln Γ(x) by the Lanczos approximation
+fn ln_gamma(x: f64) -> f64
+{
+    const COEFFICIENTS: [f64; 6] = [76.18009172947146, -86.50532032941677, 24.01409824083091,
+        -1.231739572450155, 0.1208650973866179e-2, -0.5395239384953e-5];
+
+    let tmp = x + 5.5;
+    let series = COEFFICIENTS.iter()
+        .enumerate()
+        .fold(1.000000000190015, |sum, (i, coefficient)| sum + coefficient / (x + 1.0 + i as f64));
+
+    (2.5066282746310005 * series / x).ln() + (x + 0.5) * tmp.ln() - tmp
+}
+
+
+/// Shannon entropy in bits per byte
+pub fn entropy(data: &[u8]) -> f64
+{
+    let mut histogram = [0usize; 256];
+    for &byte in data
+    {
+        histogram[byte as usize] += 1;
+    }
+
+    let length = data.len() as f64;
+    histogram.iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| count as f64 / length)
+        .map(|p| p * (1.0 / p).log2())
+        .sum()
+}
+
+
+/// The start of the zlib stream `data`, up to `INFLATE_LIMIT` times its size
+fn decompress(data: &[u8]) -> Option<Vec<u8>>
+{
+    let mut decompressed = vec![];
+    ZlibDecoder::new(data).take((data.len() * INFLATE_LIMIT) as u64).read_to_end(&mut decompressed).ok()?;
+    Some(decompressed)
+}
+
+
+/// The text or data a chunk carries, decompressed and without keyword or name where the type has them
+fn content(chunk: &Chunk) -> Vec<u8>
+{
+    let data = chunk.data();
+    let after_name = |data: &[u8]| data.iter().position(|&byte| byte == 0).map(|separator| separator + 1);
+
+    let content = match &chunk.chunk_type().to_string()[..]
+    {
+        "tEXt" => after_name(data).map(|start| data[start..].to_vec()),
+        "zTXt" | "iCCP" => after_name(data).and_then(|start| decompress(data.get(start + 1..)?)),
+        "iTXt" => after_name(data).and_then(|start| {
+            let (flag, rest) = (*data.get(start)?, data.get(start + 2..)?);
+            let language = after_name(rest)?;
+            let text = &rest[language + after_name(&rest[language..])?..];
+            if flag == 1 { decompress(text) } else { Some(text.to_vec()) }
+        }),
+        "fdAT" => data.get(4..).and_then(decompress),
+        _ => None,
+    };
+
+    content.unwrap_or_else(|| data.to_vec())
+}
+
+
+/// Whether a byte can be part of hex or base64 text
+fn is_text_byte(byte: u8) -> bool
+{
+    byte.is_ascii_alphanumeric() || byte.is_ascii_whitespace() || b"+/=".contains(&byte)
+}
+
+
+/// Replaces the `%XX` escapes of URL encoded text, as some editors store documents in text chunks
+fn percent_decode(content: &[u8]) -> Vec<u8>
+{
+    let mut decoded = Vec::with_capacity(content.len());
+    let mut rest = content;
+    while let Some((&byte, tail)) = rest.split_first()
+    {
+        let escaped = tail.get(..2)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+
+        match escaped
+        {
+            Some(escaped) if byte == b'%' => {
+                decoded.push(escaped);
+                rest = &tail[2..];
+            }
+            _ => {
+                decoded.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    decoded
+}
+
+
+/// Decodes the longest run of hex lines or base64 in `content`, as encrypted messages are often stored.
+/// Words of hex digits shorter than a line are skipped, like the name and length of a raw profile
+fn decode_text(content: &[u8]) -> Option<(&'static str, Vec<u8>)>
+{
+    let content = percent_decode(content);
+    let run = content.split(|&byte| !is_text_byte(byte)).max_by_key(|run| run.len())?;
+    let text = std::str::from_utf8(run).ok()?;
+
+    let digits: String = text.split_whitespace()
+        .filter(|word| word.len() >= 16 && word.bytes().all(|byte| byte.is_ascii_hexdigit()))
+        .collect();
+    if digits.len() >= MIN_PAYLOAD_LENGTH * 2
+    {
+        if let Ok(bytes) = hex::decode(&digits)
+        {
+            return Some(("hex", bytes));
+        }
+    }
+
+    // Words separated by spaces, or made of letters only, are more likely prose than base64
+    let base64: String = text.lines().map(str::trim).collect();
+    if base64.contains([' ', '\t']) || base64.bytes().all(|byte| byte.is_ascii_alphabetic() || byte == b'=')
+    {
+        return None;
+    }
+
+    // A few stray characters may come first, such as a length byte that happens to be a letter
+    (0..4)
+        .filter(|&skip| base64.len() >= skip + MIN_PAYLOAD_LENGTH * 4 / 3)
+        .find_map(|skip| STANDARD.decode(&base64[skip..]).ok())
+        .map(|bytes| ("base64", bytes))
+}
+
+
+/// Number of bytes a reader yields before it ends or fails, counting no further than just past `limit`
+fn readable<R: Read>(mut reader: R, limit: usize) -> usize
+{
+    let mut buffer = [0; 4096];
+    let mut total = 0;
+    while total <= limit
+    {
+        match reader.read(&mut buffer)
+        {
+            Ok(length @ 1..) => total += length,
+            _ => break,
+        }
+    }
+    total
+}
+
+
+/// Whether `data` is a zlib or raw deflate stream, possibly cut short, that inflates to more than its size
+fn inflates(data: &[u8]) -> bool
+{
+    readable(ZlibDecoder::new(data), data.len()) > data.len() || readable(DeflateDecoder::new(data), data.len()) > data.len()
+}
+
+
+/// Probability of byte counts at least as uneven as those of `data` if its bytes were uniformly random
+fn uniformity(data: &[u8]) -> f64
+{
+    let mut histogram = [0usize; 256];
+    for &byte in data
+    {
+        histogram[byte as usize] += 1;
+    }
+
+    let expected = data.len() as f64 / 256.0;
+    let statistic: f64 = histogram.iter().map(|&count| (count as f64 - expected).powi(2) / expected).sum();
+    1.0 - regularized_gamma(255.0 / 2.0, statistic / 2.0)
+}
+
+
+/// Whether `data` is as close to random as its length allows. Compressed data is nearly as dense,
+/// but it inflates, and given enough bytes their counts are far from even
+fn looks_random(data: &[u8]) -> bool
+{
+    let highest = (data.len().min(256) as f64).log2();
+    if data.len() < MIN_PAYLOAD_LENGTH || entropy(data) < highest * ENCRYPTED_ENTROPY || inflates(data)
+    {
+        return false;
+    }
+
+    data.len() < 256 * CHI_SQUARE_MIN_EXPECTED as usize || uniformity(data) > UNIFORMITY_THRESHOLD
+}
+
+
+/// Why a chunk content looks encrypted, if it does. Text is decoded up to `depth` times,
+/// for encrypted messages in base64 that were then stored as hex. Random looking bytes that are
+/// not decoded from text count only if `binary` is false
+fn encrypted(content: &[u8], depth: usize, binary: bool) -> Option<String>
+{
+    if content.starts_with(AGE_HEADER)
+    {
+        return Some(String::from("age encrypted message"));
+    }
+
+    if depth > 0
+    {
+        if let Some((encoding, decoded)) = decode_text(content)
+        {
+            if let Some(reason) = encrypted(&decoded, depth - 1, false)
+            {
+                return Some(format!("{reason} in {encoding}"));
+            }
+        }
+    }
+
+    if !binary && looks_random(content)
+    {
+        return Some(format!("{} random looking bytes", content.len()));
+    }
+
+    None
+}
+
+
+/// Entropy of the chunk data, and a look for encrypted payloads in its content. Standard chunks
+/// other than text may hold binary data such as transparency tables or color curves, which can look random
+fn analyze_chunk(index: usize, chunk: &Chunk) -> ChunkAnalysis
+{
+    let chunk_type = chunk.chunk_type();
+    let binary = chunk_type.is_standard() && !["tEXt", "zTXt", "iTXt"].contains(&&chunk_type.to_string()[..]);
+
+    ChunkAnalysis {
+        index,
+        chunk_type: chunk.chunk_type().to_string(),
+        length: chunk.data().len(),
+        entropy: entropy(chunk.data()),
+        encrypted: encrypted(&content(chunk), 2, binary),
+    }
+}
+
+
+impl fmt::Display for Analysis
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match &self.pixels
+        {
+            Some(pixels) => {
+                let chi_square: Vec<String> = pixels.chi_square.iter().map(|p| format!("{p:.2}")).collect();
+                writeln!(f, "Pixels: {} samples{}", pixels.samples, if pixels.is_suspicious() { ", suspicious" } else { "" })?;
+                writeln!(f, "  chi-square embedding probability by eighths: {}", chi_square.join(" "))?;
+                match pixels.rs_estimate
+                {
+                    Some(estimate) => writeln!(f, "  RS estimate of changed LSBs: {:.1}%", estimate * 100.0)?,
+                    None => writeln!(f, "  RS estimate of changed LSBs: none, too many flat areas")?,
+                }
+            }
+            None => writeln!(f, "Pixels: not analyzed, only 8-bit grayscale and truecolor images are")?,
+        }
+
+        writeln!(f, "Ancillary chunks: {}", self.chunks.len())?;
+        for chunk in &self.chunks
+        {
+            write!(f, "  {:>3} {} {:>8} bytes, entropy {:.2} bits/byte", chunk.index, chunk.chunk_type, chunk.length,
+                chunk.entropy)?;
+            match &chunk.encrypted
+            {
+                Some(reason) => writeln!(f, ", looks encrypted: {reason}")?,
+                None => writeln!(f)?,
+            }
+        }
+
+        write!(f, "Verdict: {}", if self.is_suspicious() { "suspicious" } else { "nothing found" })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use crate::pixels::Image;
+    use crate::png::tests::PNG_FILE;
+    use std::str::FromStr;
+
+    /// splitmix64
+    fn random(state: &mut u64) -> u64 {
+        *state = state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = *state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    fn random_bytes(length: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..length).map(|_| random(&mut state) as u8).collect()
+    }
+
+    /// A smooth image with some noise, the values of its first channel all even like after a
+    /// conversion from fewer bits
+    fn testing_image() -> Image {
+        let mut state = 1;
+        let mut image = Image::new(256, 256);
+        for y in 0..256 {
+            for x in 0..256 {
+                let noise = (random(&mut state) % 7) as f64 - 3.0;
+                let value = |shift: f64| (128.0 + 60.0 * ((x as f64 + shift) / 40.0).sin() * (y as f64 / 50.0).cos()
+                    + noise) as u8;
+                image.set_pixel(x, y, [value(0.0) & 0xFE, value(10.0), value(20.0), 255]);
+            }
+        }
+        image
+    }
+
+    /// Replaces the LSBs of the first `share` of the color samples with random bits
+    fn embed(image: &mut Image, share: f64) {
+        let mut state = 7;
+        let count = (image.pixels.len() as f64 * share) as usize;
+        for (i, sample) in image.pixels.iter_mut().take(count).enumerate() {
+            if i % 4 != 3 {
+                *sample = (*sample & 0xFE) | (random(&mut state) & 1) as u8;
+            }
+        }
+    }
+
+    #[test]
+    fn test_clean_pixels() {
+        let analysis = analyze_pixels(&testing_image(), 3);
+        assert_eq!(analysis.samples, 256 * 256 * 3);
+        assert_eq!(analysis.chi_square.len(), CHI_SQUARE_STEPS);
+        assert!(analysis.chi_square[0] < 0.5, "{analysis:?}");
+        assert!(analysis.rs_estimate.unwrap() < RS_THRESHOLD, "{analysis:?}");
+        assert!(!analysis.is_suspicious());
+    }
+
+    #[test]
+    fn test_embedded_pixels() {
+        let mut image = testing_image();
+        embed(&mut image, 1.0);
+        let analysis = analyze_pixels(&image, 3);
+        assert!(analysis.chi_square.iter().all(|&p| p > CHI_SQUARE_THRESHOLD), "{analysis:?}");
+        assert!(analysis.rs_estimate.unwrap() > 0.5, "{analysis:?}");
+
+        let mut image = testing_image();
+        embed(&mut image, 0.25);
+        let analysis = analyze_pixels(&image, 3);
+        assert!(analysis.chi_square[0] > CHI_SQUARE_THRESHOLD, "{analysis:?}");
+        assert!(analysis.chi_square[7] < 0.5, "{analysis:?}");
+        assert!((analysis.rs_estimate.unwrap() - 0.25).abs() < 0.05, "{analysis:?}");
+        assert!(analysis.is_suspicious());
+    }
+
+    #[test]
+    fn test_flat_pixels() {
+        let mut image = testing_image();
+        for y in 0..256 {
+            for x in 0..160 {
+                image.set_pixel(x, y, [255, 255, 255, 255]);
+            }
+        }
+        assert_eq!(analyze_pixels(&image, 3).rs_estimate, None);
+    }
+
+    #[test]
+    fn test_regularized_gamma() {
+        // P(1, x) = 1 - e^-x, and the chi-square CDF with 2 degrees of freedom at 5.991 is 0.95
+        assert!((regularized_gamma(1.0, 2.0) - (1.0 - (-2.0f64).exp())).abs() < 1e-9);
+        assert!((regularized_gamma(1.0, 5.991 / 2.0) - 0.95).abs() < 1e-3);
+        assert!((regularized_gamma(63.5, 63.5) - 0.5).abs() < 0.05);
+        assert_eq!(regularized_gamma(2.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(&[7; 100]), 0.0);
+        assert_eq!(entropy(&[0, 1, 2, 3]), 2.0);
+        assert!(entropy(&random_bytes(4096, 3)) > 7.9);
+
+        assert!(looks_random(&random_bytes(4096, 3)));
+        let mut skewed = random_bytes(4096, 3);
+        skewed.iter_mut().step_by(8).for_each(|byte| *byte = 0xFF);
+        assert!(entropy(&skewed) > 7.2 && !looks_random(&skewed));
+    }
+
+    #[test]
+    fn test_encrypted_chunks() {
+        let chunk = |chunk_type: &str, data: &[u8]| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec());
+        let secret = random_bytes(48, 5);
+
+        let base64 = STANDARD.encode(&secret);
+        assert!(analyze_chunk(0, &chunk("ruSt", base64.as_bytes())).encrypted.is_some());
+        assert!(analyze_chunk(0, &chunk("ruSt", &secret)).encrypted.is_some());
+        assert!(analyze_chunk(0, &chunk("ruSt", b"age-encryption.org/v1\n-> X25519")).encrypted.is_some());
+
+        let raw_profile = format!("Raw profile type iptc\0\niptc\n      48\n{}\n{}\n", hex::encode(&secret[..36]),
+            hex::encode(&secret[36..]));
+        assert!(analyze_chunk(0, &chunk("tEXt", raw_profile.as_bytes())).encrypted.is_some());
+
+        // Base64 stored as hex, and base64 after a binary length as in a palette
+        let nested = format!("Raw profile type iptc\0\niptc\n      64\n{}\n", hex::encode(&base64));
+        assert!(analyze_chunk(0, &chunk("tEXt", nested.as_bytes())).encrypted.unwrap().ends_with("in base64 in hex"));
+        let mut palette = b"Optimized\0\x08\0\0\0\x41".to_vec();
+        palette.extend(base64.as_bytes());
+        assert!(analyze_chunk(0, &chunk("sPLT", &palette)).encrypted.is_some());
+
+        let text = b"Comment\0A perfectly ordinary comment about the picture, written by a person who took it.";
+        assert!(analyze_chunk(0, &chunk("tEXt", text)).encrypted.is_none());
+        assert!(analyze_chunk(0, &chunk("ruSt", b"hello")).encrypted.is_none());
+        assert!(analyze_chunk(0, &chunk("tRNS", &secret)).encrypted.is_none());
+        let mut compressed = vec![];
+        let plain = random_bytes(64, 1).repeat(8);
+        flate2::read::DeflateEncoder::new(&plain[..], Default::default()).read_to_end(&mut compressed).unwrap();
+        assert!(analyze_chunk(0, &chunk("ruSt", STANDARD.encode(&compressed).as_bytes())).encrypted.is_none());
+        let document = format!("mxfile\0%3Cdiagram%3E{}%3C%2Fdiagram%3E", STANDARD.encode(&compressed).replace('/', "%2F"));
+        assert!(analyze_chunk(0, &chunk("tEXt", document.as_bytes())).encrypted.is_none());
+        let prose = b"meet me at the old bridge at midnight and bring the documents";
+        assert!(analyze_chunk(0, &chunk("ruSt", prose)).encrypted.is_none());
+        assert!(analyze_chunk(0, &chunk("ruSt", b"meetmeattheoldbridgeatmidnightandbringthedocuments")).encrypted.is_none());
+    }
+
+    #[test]
+    fn test_inflate_limit() {
+        let mut bomb = vec![];
+        flate2::read::ZlibEncoder::new(&vec![0; 1 << 24][..], Default::default()).read_to_end(&mut bomb).unwrap();
+        assert_eq!(decompress(&bomb).unwrap().len(), bomb.len() * INFLATE_LIMIT);
+        assert!(readable(ZlibDecoder::new(&bomb[..]), bomb.len()) < bomb.len() + 8192);
+        assert!(inflates(&bomb));
+    }
+
+    #[test]
+    fn test_analyze_png() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let analysis = analyze(&png).unwrap();
+        assert!(analysis.chunks.iter().all(|chunk| !ChunkType::from_str(&chunk.chunk_type).unwrap().is_critical()));
+        assert!(!analysis.to_string().is_empty());
+
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), random_bytes(256, 9)));
+        let analysis = analyze(&png).unwrap();
+        assert!(analysis.is_suspicious());
+        assert!(analysis.chunks.last().unwrap().encrypted.is_some());
+    }
+}
@@ -0,0 +1,810 @@
+use crate::chunk::Chunk;
+use crate::ihdr::ImageHeader;
+
+use crate::error::{Error, Result, bail};
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::vec;
+
+use core::fmt;
+
+#[cfg(feature = "compression")]
+use crate::chunk_type::ChunkType;
+#[cfg(feature = "compression")]
+use crate::pixels::{self, Image};
+#[cfg(feature = "compression")]
+use crate::png::Png;
+#[cfg(feature = "compression")]
+use std::str::FromStr;
+
+
+/// The animation control chunk `acTL`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnimationControl
+{
+    pub num_frames: u32,
+    pub num_plays: u32,
+}
+
+
+/// How the frame area is disposed of before rendering the next frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisposeOp
+{
+    None,
+    Background,
+    Previous,
+}
+
+
+/// How the frame is blended into the output buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendOp
+{
+    Source,
+    Over,
+}
+
+
+/// The frame control chunk `fcTL`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameControl
+{
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp,
+}
+
+
+/// The frame data chunk `fdAT`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameData<'a>
+{
+    pub sequence_number: u32,
+    pub data: &'a [u8],
+}
+
+
+/// A single animation frame: its control chunk and the image data belonging to it
+#[derive(Debug, Clone)]
+pub struct Frame<'a>
+{
+    pub control: FrameControl,
+    /// Whether the frame data is stored in `IDAT`, making it the default image as well
+    pub is_default_image: bool,
+    /// The zlib stream pieces of the frame, with `fdAT` sequence numbers stripped
+    pub data: Vec<&'a [u8]>,
+}
+
+
+pub const ACTL: &str = "acTL";
+pub const FCTL: &str = "fcTL";
+pub const FDAT: &str = "fdAT";
+
+
+/// Returns true if the chunk type carries an APNG sequence number or controls the animation
+pub fn is_animation_chunk(chunk_type: &str) -> bool
+{
+    chunk_type == ACTL || chunk_type == FCTL || chunk_type == FDAT
+}
+
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32>
+{
+    Ok(u32::from_be_bytes(data[offset..offset + 4].try_into()?))
+}
+
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16>
+{
+    Ok(u16::from_be_bytes(data[offset..offset + 2].try_into()?))
+}
+
+
+fn expect_type(chunk: &Chunk, expected: &str) -> Result<()>
+{
+    let chunk_type = chunk.chunk_type().to_string();
+    if chunk_type != expected
+    {
+        bail!("Expected {expected} chunk, found {chunk_type}")
+    }
+
+    Ok(())
+}
+
+
+impl AnimationControl
+{
+    pub const LENGTH: usize = 8;
+
+    pub fn to_bytes(&self) -> Vec<u8>
+    {
+        [self.num_frames, self.num_plays]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect()
+    }
+}
+
+
+impl TryFrom<&Chunk> for AnimationControl
+{
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self>
+    {
+        expect_type(chunk, ACTL)?;
+
+        let data = chunk.data();
+        if data.len() != Self::LENGTH
+        {
+            bail!("Invalid {ACTL} length {}", data.len())
+        }
+
+        let control = Self {
+            num_frames: read_u32(data, 0)?,
+            num_plays: read_u32(data, 4)?,
+        };
+
+        if control.num_frames == 0
+        {
+            bail!("Animation must have at least one frame")
+        }
+
+        Ok(control)
+    }
+}
+
+
+impl TryFrom<u8> for DisposeOp
+{
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self>
+    {
+        match byte
+        {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Background),
+            2 => Ok(Self::Previous),
+            _ => bail!("Invalid dispose op {byte}"),
+        }
+    }
+}
+
+
+impl TryFrom<u8> for BlendOp
+{
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self>
+    {
+        match byte
+        {
+            0 => Ok(Self::Source),
+            1 => Ok(Self::Over),
+            _ => bail!("Invalid blend op {byte}"),
+        }
+    }
+}
+
+
+impl FrameControl
+{
+    pub const LENGTH: usize = 26;
+
+    /// The frame delay in seconds. A zero denominator means 1/100 s per the APNG spec
+    pub fn delay(&self) -> f64
+    {
+        let den = if self.delay_den == 0 { 100 } else { self.delay_den };
+        self.delay_num as f64 / den as f64
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8>
+    {
+        let mut bytes = Vec::with_capacity(Self::LENGTH);
+        for value in [self.sequence_number, self.width, self.height, self.x_offset, self.y_offset]
+        {
+            bytes.extend_from_slice(&value.to_be_bytes());
+        }
+        bytes.extend_from_slice(&self.delay_num.to_be_bytes());
+        bytes.extend_from_slice(&self.delay_den.to_be_bytes());
+        bytes.push(self.dispose_op as u8);
+        bytes.push(self.blend_op as u8);
+
+        bytes
+    }
+}
+
+
+impl TryFrom<&Chunk> for FrameControl
+{
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self>
+    {
+        expect_type(chunk, FCTL)?;
+
+        let data = chunk.data();
+        if data.len() != Self::LENGTH
+        {
+            bail!("Invalid {FCTL} length {}", data.len())
+        }
+
+        let control = Self {
+            sequence_number: read_u32(data, 0)?,
+            width: read_u32(data, 4)?,
+            height: read_u32(data, 8)?,
+            x_offset: read_u32(data, 12)?,
+            y_offset: read_u32(data, 16)?,
+            delay_num: read_u16(data, 20)?,
+            delay_den: read_u16(data, 22)?,
+            dispose_op: DisposeOp::try_from(data[24])?,
+            blend_op: BlendOp::try_from(data[25])?,
+        };
+
+        if control.width == 0 || control.height == 0
+        {
+            bail!("Invalid frame dimensions {}x{}", control.width, control.height)
+        }
+
+        Ok(control)
+    }
+}
+
+
+impl<'a> TryFrom<&'a Chunk> for FrameData<'a>
+{
+    type Error = Error;
+
+    fn try_from(chunk: &'a Chunk) -> Result<Self>
+    {
+        expect_type(chunk, FDAT)?;
+
+        let data = chunk.data();
+        if data.len() < 4
+        {
+            bail!("Invalid {FDAT} length {}", data.len())
+        }
+
+        Ok(Self {
+            sequence_number: read_u32(data, 0)?,
+            data: &data[4..],
+        })
+    }
+}
+
+
+/// Returns the animation control of the chunks, if the image is animated
+pub fn animation_control(chunks: &[Chunk]) -> Result<Option<AnimationControl>>
+{
+    chunks.iter()
+        .find(|c| c.chunk_type().to_string() == ACTL)
+        .map(AnimationControl::try_from)
+        .transpose()
+}
+
+
+/// Groups the frame control and data chunks into frames
+pub fn frames(chunks: &[Chunk]) -> Result<Vec<Frame<'_>>>
+{
+    let mut frames: Vec<Frame> = vec![];
+
+    for chunk in chunks
+    {
+        match &chunk.chunk_type().to_string()[..]
+        {
+            FCTL => frames.push(Frame {
+                control: FrameControl::try_from(chunk)?,
+                is_default_image: false,
+                data: vec![],
+            }),
+            FDAT => {
+                let frame_data = FrameData::try_from(chunk)?;
+                match frames.last_mut()
+                {
+                    Some(frame) if !frame.is_default_image => frame.data.push(frame_data.data),
+                    _ => bail!("{FDAT} chunk {} without preceding {FCTL}", frame_data.sequence_number),
+                }
+            }
+            "IDAT" => {
+                if let [frame] = &mut frames[..]
+                {
+                    if frame.is_default_image || frame.data.is_empty()
+                    {
+                        frame.is_default_image = true;
+                        frame.data.push(chunk.data());
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(frames)
+}
+
+
+/// Validates the APNG chunks: ordering, sequence numbers, frame count and frame bounds
+pub fn validate(chunks: &[Chunk]) -> Result<()>
+{
+    let control = match animation_control(chunks)?
+    {
+        Some(control) => control,
+        None => {
+            if let Some(chunk) = chunks.iter().find(|c| is_animation_chunk(&c.chunk_type().to_string()))
+            {
+                bail!("Found {} chunk without {ACTL}", chunk.chunk_type())
+            }
+            return Ok(());
+        }
+    };
+
+    let header = match chunks.first()
+    {
+        Some(chunk) => ImageHeader::try_from(chunk)?,
+        None => bail!("Missing IHDR chunk"),
+    };
+
+    let mut seen_actl = false;
+    let mut seen_idat = false;
+    let mut next_sequence = 0;
+
+    for chunk in chunks
+    {
+        let chunk_type = chunk.chunk_type().to_string();
+        let sequence_number = match &chunk_type[..]
+        {
+            ACTL => {
+                if seen_actl
+                {
+                    bail!("Multiple {ACTL} chunks")
+                }
+                if seen_idat
+                {
+                    bail!("{ACTL} chunk after IDAT")
+                }
+                seen_actl = true;
+                None
+            }
+            FCTL => Some(FrameControl::try_from(chunk)?.sequence_number),
+            FDAT => {
+                if !seen_idat
+                {
+                    bail!("{FDAT} chunk before IDAT")
+                }
+                Some(FrameData::try_from(chunk)?.sequence_number)
+            }
+            "IDAT" => {
+                seen_idat = true;
+                None
+            }
+            _ => None,
+        };
+
+        if let Some(sequence_number) = sequence_number
+        {
+            if sequence_number != next_sequence
+            {
+                bail!("{chunk_type} has sequence number {sequence_number}, expected {next_sequence}")
+            }
+            next_sequence += 1;
+        }
+    }
+
+    let frames = frames(chunks)?;
+    if frames.len() != control.num_frames as usize
+    {
+        bail!("{ACTL} declares {} frames, found {}", control.num_frames, frames.len())
+    }
+
+    for (index, frame) in frames.iter().enumerate()
+    {
+        let fc = &frame.control;
+        if fc.x_offset as u64 + fc.width as u64 > header.width as u64
+            || fc.y_offset as u64 + fc.height as u64 > header.height as u64
+        {
+            bail!("Frame {index} exceeds the image bounds")
+        }
+
+        if frame.is_default_image
+            && (fc.x_offset != 0 || fc.y_offset != 0 || fc.width != header.width || fc.height != header.height)
+        {
+            bail!("Frame {index} is the default image but does not cover it")
+        }
+
+        if frame.data.is_empty()
+        {
+            bail!("Frame {index} has no image data")
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Composites every frame onto the canvas, applying the blend and dispose ops,
+/// and returns a full canvas snapshot per frame
+#[cfg(feature = "compression")]
+pub fn render(png: &Png) -> anyhow::Result<Vec<(Image, FrameControl)>>
+{
+    png.validate_animation()?;
+
+    let header = png.image_header()?;
+    let mut canvas = Image::try_new(header.width, header.height)?;
+    let mut rendered = vec![];
+
+    for (index, frame) in png.frames()?.into_iter().enumerate()
+    {
+        let fc = frame.control;
+        let frame_header = ImageHeader {width: fc.width, height: fc.height, ..header.clone()};
+        let image = pixels::decode_data(png, &frame_header, &frame.data)?;
+
+        let previous = match fc.dispose_op
+        {
+            DisposeOp::Previous if index > 0 => Some(canvas.clone()),
+            _ => None,
+        };
+
+        for y in 0..fc.height
+        {
+            for x in 0..fc.width
+            {
+                let (cx, cy) = (fc.x_offset + x, fc.y_offset + y);
+                let source = image.pixel(x, y);
+                let pixel = match fc.blend_op
+                {
+                    BlendOp::Source => source,
+                    BlendOp::Over => blend_over(source, canvas.pixel(cx, cy)),
+                };
+                canvas.set_pixel(cx, cy, pixel);
+            }
+        }
+
+        rendered.push((canvas.clone(), fc.clone()));
+
+        match (fc.dispose_op, previous)
+        {
+            (DisposeOp::Previous, Some(previous)) => canvas = previous,
+            (DisposeOp::None, _) => (),
+            _ => {
+                for y in fc.y_offset..fc.y_offset + fc.height
+                {
+                    for x in fc.x_offset..fc.x_offset + fc.width
+                    {
+                        canvas.set_pixel(x, y, [0; 4]);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(rendered)
+}
+
+
+/// Alpha composites `source` over `destination`, both with straight alpha
+#[cfg(feature = "compression")]
+fn blend_over(source: [u8; 4], destination: [u8; 4]) -> [u8; 4]
+{
+    let sa = source[3] as u32;
+    let da = destination[3] as u32 * (255 - sa) / 255;
+    let alpha = sa + da;
+    if alpha == 0
+    {
+        return [0; 4];
+    }
+
+    let mut result = [0, 0, 0, alpha as u8];
+    for i in 0..3
+    {
+        result[i] = ((source[i] as u32 * sa + destination[i] as u32 * da) / alpha) as u8;
+    }
+
+    result
+}
+
+
+/// Assembles full-canvas frames into an APNG. The first frame doubles as the default image.
+/// `delays` are in milliseconds; a single delay applies to all frames
+#[cfg(feature = "compression")]
+pub fn build(images: &[Image], delays: &[u16], num_plays: u32) -> anyhow::Result<Png>
+{
+    let first = match images.first()
+    {
+        Some(first) => first,
+        None => anyhow::bail!("No frames to assemble"),
+    };
+
+    if delays.len() != 1 && delays.len() != images.len()
+    {
+        anyhow::bail!("Expected 1 or {} delays, found {}", images.len(), delays.len())
+    }
+
+    let mut chunks = first.to_png()?.chunks()[..1].to_vec();
+    let control = AnimationControl {num_frames: images.len() as u32, num_plays};
+    chunks.push(Chunk::new(ChunkType::from_str(ACTL)?, control.to_bytes()));
+
+    let mut sequence_number = 0;
+    for (index, image) in images.iter().enumerate()
+    {
+        if (image.width, image.height) != (first.width, first.height)
+        {
+            anyhow::bail!("Frame {index} is {}x{}, expected {}x{}", image.width, image.height, first.width, first.height)
+        }
+
+        let control = FrameControl {
+            sequence_number,
+            width: image.width,
+            height: image.height,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: delays[index.min(delays.len() - 1)],
+            delay_den: 1000,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source,
+        };
+        chunks.push(Chunk::new(ChunkType::from_str(FCTL)?, control.to_bytes()));
+        sequence_number += 1;
+
+        let data = image.compress()?;
+        if index == 0
+        {
+            chunks.push(Chunk::new(ChunkType::from_str("IDAT")?, data));
+        }
+        else
+        {
+            let bytes = sequence_number.to_be_bytes().iter().chain(&data).copied().collect();
+            chunks.push(Chunk::new(ChunkType::from_str(FDAT)?, bytes));
+            sequence_number += 1;
+        }
+    }
+
+    chunks.push(Chunk::new(ChunkType::from_str("IEND")?, vec![]));
+
+    let png = Png::from_chunks(chunks);
+    png.validate_animation()?;
+
+    Ok(png)
+}
+
+
+impl fmt::Display for AnimationControl
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        let plays = match self.num_plays
+        {
+            0 => String::from("infinite"),
+            n => n.to_string(),
+        };
+
+        writeln!(f, "Animation {{",)?;
+        writeln!(f, "  Frames: {}", self.num_frames)?;
+        writeln!(f, "  Plays: {plays}")?;
+        writeln!(f, "}}",)?;
+        Ok(())
+    }
+}
+
+
+impl fmt::Display for Frame<'_>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        let fc = &self.control;
+        let bytes: usize = self.data.iter().map(|d| d.len()).sum();
+
+        writeln!(f, "Frame {{",)?;
+        writeln!(f, "  Sequence: {}", fc.sequence_number)?;
+        writeln!(f, "  Size: {}x{} at ({}, {})", fc.width, fc.height, fc.x_offset, fc.y_offset)?;
+        writeln!(f, "  Delay: {}/{} s", fc.delay_num, if fc.delay_den == 0 { 100 } else { fc.delay_den })?;
+        writeln!(f, "  Dispose: {:?}", fc.dispose_op)?;
+        writeln!(f, "  Blend: {:?}", fc.blend_op)?;
+        writeln!(f, "  Data: {bytes} bytes in {} chunks{}", self.data.len(),
+            if self.is_default_image { " (default image)" } else { "" })?;
+        writeln!(f, "}}",)?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn frame_control(sequence_number: u32, width: u32, height: u32) -> FrameControl {
+        FrameControl {
+            sequence_number,
+            width,
+            height,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source,
+        }
+    }
+
+    fn fdat(sequence_number: u32, data: &[u8]) -> Chunk {
+        let bytes = sequence_number.to_be_bytes().iter().chain(data).copied().collect();
+        chunk(FDAT, bytes)
+    }
+
+    pub(crate) fn testing_apng_chunks() -> Vec<Chunk> {
+        vec![
+            chunk("IHDR", vec![0, 0, 0, 4, 0, 0, 0, 4, 8, 6, 0, 0, 0]),
+            chunk(ACTL, AnimationControl { num_frames: 2, num_plays: 0 }.to_bytes()),
+            chunk(FCTL, frame_control(0, 4, 4).to_bytes()),
+            chunk("IDAT", vec![1, 2, 3]),
+            chunk(FCTL, frame_control(1, 2, 2).to_bytes()),
+            fdat(2, &[4, 5, 6]),
+            chunk("IEND", vec![]),
+        ]
+    }
+
+    #[test]
+    fn test_frame_control_round_trip() {
+        let control = frame_control(7, 3, 2);
+        let parsed = FrameControl::try_from(&chunk(FCTL, control.to_bytes())).unwrap();
+        assert_eq!(parsed, control);
+    }
+
+    #[test]
+    fn test_frame_control_invalid_ops() {
+        let mut bytes = frame_control(0, 1, 1).to_bytes();
+        bytes[24] = 3;
+        assert!(FrameControl::try_from(&chunk(FCTL, bytes)).is_err());
+    }
+
+    #[test]
+    fn test_frame_data() {
+        let chunk = fdat(5, &[9, 8]);
+        let data = FrameData::try_from(&chunk).unwrap();
+        assert_eq!(data.sequence_number, 5);
+        assert_eq!(data.data, &[9, 8]);
+    }
+
+    #[test]
+    fn test_frames() {
+        let chunks = testing_apng_chunks();
+        let frames = frames(&chunks).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert!(frames[0].is_default_image);
+        assert_eq!(frames[0].data, vec![&[1u8, 2, 3][..]]);
+        assert!(!frames[1].is_default_image);
+        assert_eq!(frames[1].data, vec![&[4u8, 5, 6][..]]);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&testing_apng_chunks()).is_ok());
+    }
+
+    #[test]
+    fn test_validate_bad_sequence() {
+        let mut chunks = testing_apng_chunks();
+        chunks[5] = fdat(3, &[4, 5, 6]);
+        assert!(validate(&chunks).is_err());
+    }
+
+    #[test]
+    fn test_validate_frame_count() {
+        let mut chunks = testing_apng_chunks();
+        chunks[1] = chunk(ACTL, AnimationControl { num_frames: 3, num_plays: 0 }.to_bytes());
+        assert!(validate(&chunks).is_err());
+    }
+
+    #[test]
+    fn test_validate_frame_bounds() {
+        let mut chunks = testing_apng_chunks();
+        chunks[4] = chunk(FCTL, frame_control(1, 5, 2).to_bytes());
+        assert!(validate(&chunks).is_err());
+    }
+
+    #[test]
+    fn test_validate_missing_actl() {
+        let mut chunks = testing_apng_chunks();
+        chunks.remove(1);
+        assert!(validate(&chunks).is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    fn solid(width: u32, height: u32, rgba: [u8; 4]) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set_pixel(x, y, rgba);
+            }
+        }
+        image
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_build_and_render() {
+        let images = vec![solid(4, 3, [255, 0, 0, 255]), solid(4, 3, [0, 0, 255, 128])];
+        let png = build(&images, &[100, 250], 0).unwrap();
+
+        let png = Png::try_from(&png.as_bytes()[..]).unwrap();
+        assert!(png.validate_animation().is_ok());
+
+        let frames = render(&png).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].0, images[0]);
+        assert_eq!(frames[1].0, images[1]);
+        assert_eq!(frames[1].1.delay_num, 250);
+        assert_eq!(frames[1].1.delay_den, 1000);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_build_size_mismatch() {
+        let images = vec![solid(4, 3, [0; 4]), solid(3, 3, [0; 4])];
+        assert!(build(&images, &[100], 0).is_err());
+        assert!(build(&[], &[100], 0).is_err());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_render_blend_and_dispose() {
+        let background = solid(4, 4, [255, 0, 0, 255]);
+        let mut png = build(&[background.clone(), background.clone(), background], &[100], 1).unwrap();
+
+        // Replace the second and third frames with a 2x2 half transparent blue square
+        // blended over the canvas, disposed to the previous canvas
+        let patch = solid(2, 2, [0, 0, 255, 128]).compress().unwrap();
+        let mut chunks = png.chunks().to_vec();
+        for (control_index, data_index) in [(4, 5), (6, 7)] {
+            let mut control = FrameControl::try_from(&chunks[control_index]).unwrap();
+            control.width = 2;
+            control.height = 2;
+            control.x_offset = 1;
+            control.y_offset = 1;
+            control.blend_op = BlendOp::Over;
+            control.dispose_op = DisposeOp::Previous;
+            chunks[data_index] = fdat(control.sequence_number + 1, &patch);
+            chunks[control_index] = chunk(FCTL, control.to_bytes());
+        }
+        png = Png::from_chunks(chunks);
+
+        let frames = render(&png).unwrap();
+        assert_eq!(frames[1].0.pixel(0, 0), [255, 0, 0, 255]);
+        assert_eq!(frames[1].0.pixel(1, 1), [127, 0, 128, 255]);
+        // The previous frame was restored before blending the third frame
+        assert_eq!(frames[2].0, frames[1].0);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_blend_over() {
+        assert_eq!(blend_over([0, 0, 0, 0], [10, 20, 30, 255]), [10, 20, 30, 255]);
+        assert_eq!(blend_over([10, 20, 30, 255], [0, 0, 0, 255]), [10, 20, 30, 255]);
+        assert_eq!(blend_over([0; 4], [0; 4]), [0; 4]);
+    }
+
+    #[test]
+    fn test_validate_not_animated() {
+        let chunks = vec![
+            chunk("IHDR", vec![0, 0, 0, 4, 0, 0, 0, 4, 8, 6, 0, 0, 0]),
+            chunk("IDAT", vec![1, 2, 3]),
+            chunk("IEND", vec![]),
+        ];
+        assert!(validate(&chunks).is_ok());
+    }
+}
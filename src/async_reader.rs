@@ -0,0 +1,244 @@
+use crate::chunk::{self, Chunk, StreamingCrc};
+use crate::chunk_type::ChunkType;
+use crate::ihdr::ImageHeader;
+use crate::png::Png;
+
+use anyhow::{Context, Result, bail};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+
+/// Where the reader is in the PNG stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State
+{
+    Signature,
+    FirstChunk,
+    Chunks,
+    End,
+}
+
+
+/// Reads the chunks of a PNG from an async byte stream one at a time, so that a caller
+/// can stop as soon as it has what it needs. Chunks are checked like in `Png::try_from`
+pub struct AsyncChunkReader<R>
+{
+    reader: R,
+    state: State,
+    max_length: usize,
+}
+
+
+impl<R: AsyncRead + Unpin> AsyncChunkReader<R>
+{
+    /// Largest chunk length allowed by the PNG specification
+    pub const MAX_LENGTH: usize = Chunk::MAX_LENGTH;
+
+    pub fn new(reader: R) -> Self
+    {
+        Self {reader, state: State::Signature, max_length: Self::MAX_LENGTH}
+    }
+
+    /// Refuses chunks longer than `max_length` bytes before reading their data
+    pub fn with_max_length(mut self, max_length: usize) -> Self
+    {
+        self.max_length = max_length;
+        self
+    }
+
+    pub fn into_inner(self) -> R
+    {
+        self.reader
+    }
+
+    async fn read_signature(&mut self) -> Result<()>
+    {
+        let mut header = [0; 8];
+        self.reader.read_exact(&mut header).await.context("Unexpected end of the PNG signature")?;
+        Png::check_header(&header)?;
+
+        self.state = State::FirstChunk;
+        Ok(())
+    }
+
+    /// Reads the length and type of the next chunk, or returns `None` after `IEND`
+    async fn next_chunk_head(&mut self) -> Result<Option<(usize, ChunkType, [u8; 8])>>
+    {
+        match self.state
+        {
+            State::Signature => self.read_signature().await?,
+            State::End => return Ok(None),
+            _ => (),
+        }
+
+        let mut head = [0; 8];
+        self.reader.read_exact(&mut head).await.context("Unexpected end of the PNG stream before IEND")?;
+
+        let (length, chunk_type) = chunk::parse_head(&head, self.max_length)?;
+
+        self.state = if chunk_type.bytes() == *b"IEND" { State::End } else { State::Chunks };
+        Ok(Some((length, chunk_type, head)))
+    }
+
+    /// Reads the data and CRC of a chunk whose head was read, and checks it like `Chunk::try_from`
+    async fn read_chunk_body(&mut self, length: usize, head: [u8; 8]) -> Result<Chunk>
+    {
+        let mut bytes = vec![0; length + Chunk::OVERHEAD];
+        bytes[..8].copy_from_slice(&head);
+        self.reader.read_exact(&mut bytes[8..]).await.context("Unexpected end of chunk data")?;
+
+        Ok(Chunk::try_from(&bytes[..])?)
+    }
+
+    /// Reads the next chunk, or returns `None` after `IEND`
+    pub async fn next_chunk(&mut self) -> Result<Option<Chunk>>
+    {
+        match self.next_chunk_head().await?
+        {
+            Some((length, _, head)) => Ok(Some(self.read_chunk_body(length, head).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads past the data of a chunk without keeping it, checking its CRC on the way
+    async fn skip_chunk(&mut self, length: usize, chunk_type: ChunkType) -> Result<()>
+    {
+        let mut crc = StreamingCrc::new(chunk_type);
+        let mut buffer = vec![0; length.min(chunk::SKIP_BUFFER)];
+        let mut remaining = length;
+        while remaining > 0
+        {
+            let piece = &mut buffer[..remaining.min(chunk::SKIP_BUFFER)];
+            self.reader.read_exact(piece).await.context("Unexpected end of chunk data")?;
+            crc.update(piece);
+            remaining -= piece.len();
+        }
+
+        let mut stored = [0; 4];
+        self.reader.read_exact(&mut stored).await.context("Unexpected end of chunk data")?;
+        Ok(crc.verify(stored)?)
+    }
+
+    /// Reads chunks up to the first one of `chunk_type` and returns it, without keeping
+    /// the data of the chunks before it. Returns `None` if `IEND` comes first
+    pub async fn find_chunk(&mut self, chunk_type: &str) -> Result<Option<Chunk>>
+    {
+        let wanted = ChunkType::try_from(<[u8; 4]>::try_from(chunk_type.as_bytes())?)?;
+
+        while let Some((length, found, head)) = self.next_chunk_head().await?
+        {
+            if found != wanted
+            {
+                self.skip_chunk(length, found).await?;
+                continue;
+            }
+
+            return Ok(Some(self.read_chunk_body(length, head).await?));
+        }
+
+        Ok(None)
+    }
+
+    /// Reads the signature and the `IHDR` chunk, which must come first, and nothing more
+    pub async fn read_header(&mut self) -> Result<ImageHeader>
+    {
+        if !matches!(self.state, State::Signature | State::FirstChunk)
+        {
+            bail!("The image header must be read before any other chunk");
+        }
+
+        match self.next_chunk().await?
+        {
+            Some(chunk) => Ok(ImageHeader::try_from(&chunk)?),
+            None => bail!("Missing IHDR chunk"),
+        }
+    }
+
+    /// Reads the whole stream into a `Png`, including any trailer after `IEND`
+    pub async fn read_png(mut self) -> Result<Png>
+    {
+        if self.state != State::Signature
+        {
+            bail!("Only an unread stream can be read as a whole PNG");
+        }
+
+        let mut chunks = vec![];
+        while let Some(chunk) = self.next_chunk().await?
+        {
+            chunks.push(chunk);
+        }
+
+        let mut trailer = vec![];
+        self.reader.read_to_end(&mut trailer).await?;
+
+        let mut png = Png::from_chunks(chunks);
+        png.set_trailer(trailer);
+        Ok(png)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn test_read_png() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"trailer");
+
+        let png = run(AsyncChunkReader::new(&bytes[..]).read_png()).unwrap();
+        assert_eq!(png.as_bytes(), bytes);
+        assert_eq!(png.trailer(), b"trailer");
+    }
+
+    #[test]
+    fn test_next_chunk() {
+        let mut reader = AsyncChunkReader::new(&PNG_FILE[..]);
+        let expected = Png::try_from(&PNG_FILE[..]).unwrap();
+
+        for chunk in expected.chunks() {
+            let read = run(reader.next_chunk()).unwrap().unwrap();
+            assert_eq!(read.as_bytes(), chunk.as_bytes());
+        }
+        assert!(run(reader.next_chunk()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_header_stops_early() {
+        // Everything after IHDR is garbage that must not be read
+        let mut bytes = PNG_FILE[..33].to_vec();
+        bytes.extend_from_slice(&[0xff; 64]);
+
+        let mut reader = AsyncChunkReader::new(&bytes[..]);
+        let header = run(reader.read_header()).unwrap();
+        assert_eq!(header, Png::try_from(&PNG_FILE[..]).unwrap().image_header().unwrap());
+        assert_eq!(reader.into_inner().len(), 64);
+    }
+
+    #[test]
+    fn test_find_chunk() {
+        let expected = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut reader = AsyncChunkReader::new(&PNG_FILE[..]);
+
+        let chunk = run(reader.find_chunk("IEND")).unwrap().unwrap();
+        assert_eq!(chunk.as_bytes(), expected.chunk_by_type("IEND").unwrap().as_bytes());
+        assert!(run(AsyncChunkReader::new(&PNG_FILE[..]).find_chunk("ruSt")).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid_stream() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[40] ^= 1;
+        assert!(run(AsyncChunkReader::new(&bytes[..]).read_png()).is_err());
+        assert!(run(AsyncChunkReader::new(&bytes[..]).find_chunk("IEND")).is_err());
+
+        assert!(run(AsyncChunkReader::new(&PNG_FILE[1..]).read_png()).is_err());
+        assert!(run(AsyncChunkReader::new(&PNG_FILE[..100]).read_png()).is_err());
+        assert!(run(AsyncChunkReader::new(&PNG_FILE[..]).with_max_length(4).read_png()).is_err());
+    }
+}
@@ -0,0 +1,501 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+use anyhow::{Context, Result, bail};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use std::fmt;
+use std::io::prelude::*;
+use std::str::FromStr;
+
+
+/// Keyword of the `zTXt` carrier, as written by ImageMagick for embedded IPTC metadata
+const TEXT_KEYWORD: &str = "Raw profile type iptc";
+
+/// Profile name of the `iCCP` carrier
+const PROFILE_NAME: &str = "ICC Profile";
+
+/// Palette name of the `sPLT` carrier
+const PALETTE_NAME: &str = "Optimized";
+
+/// Size of an ICC profile header, followed by the tag count
+const PROFILE_HEADER_LENGTH: usize = 128;
+
+/// Size of an ICC tag table entry: signature, offset and size
+const PROFILE_TAG_LENGTH: usize = 12;
+
+/// Bytes of an `sPLT` entry of sample depth 8: RGBA and frequency
+const PALETTE_ENTRY_LENGTH: usize = 6;
+
+/// Hex digits per line of a raw profile
+const HEX_LINE_LENGTH: usize = 72;
+
+/// Most bytes inflated per compressed byte of a carrier, far more than the hex or profile around an
+/// encrypted payload compresses from
+const INFLATE_LIMIT: usize = 64;
+
+
+/// Standard chunk that a payload is disguised as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Carrier
+{
+    /// A `zTXt` raw metadata profile in hex, as ImageMagick writes it
+    Text,
+    /// Padding after the tag data of an sRGB ICC profile in `iCCP`, before `PLTE` and `IDAT`
+    Profile,
+    /// The entries of a suggested palette in `sPLT`, before `IDAT`
+    Palette,
+}
+
+
+impl Carrier
+{
+    pub const ALL: [Carrier; 3] = [Carrier::Text, Carrier::Profile, Carrier::Palette];
+
+    pub fn chunk_type(&self) -> &'static str
+    {
+        match self
+        {
+            Carrier::Text => "zTXt",
+            Carrier::Profile => "iCCP",
+            Carrier::Palette => "sPLT",
+        }
+    }
+}
+
+
+impl FromStr for Carrier
+{
+    type Err = anyhow::Error;
+
+    /// Parses the chunk type of a carrier
+    fn from_str(chunk_type: &str) -> Result<Self>
+    {
+        match Carrier::ALL.into_iter().find(|carrier| carrier.chunk_type() == chunk_type)
+        {
+            Some(carrier) => Ok(carrier),
+            None => bail!("{chunk_type} cannot carry a disguised message, expected zTXt, iCCP or sPLT"),
+        }
+    }
+}
+
+
+impl fmt::Display for Carrier
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        f.write_str(self.chunk_type())
+    }
+}
+
+
+fn compress(data: &[u8]) -> Result<Vec<u8>>
+{
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+
+/// Inflates `data`, failing if it grows past `INFLATE_LIMIT` times its size
+fn decompress(data: &[u8]) -> Result<Vec<u8>>
+{
+    let limit = data.len() * INFLATE_LIMIT;
+    let mut decompressed = vec![];
+    ZlibDecoder::new(data).take(limit as u64 + 1).read_to_end(&mut decompressed).context("Invalid zlib stream")?;
+    if decompressed.len() > limit
+    {
+        bail!("The zlib stream inflates to more than {INFLATE_LIMIT} times its size");
+    }
+
+    Ok(decompressed)
+}
+
+
+/// Splits the data of a `zTXt` or `iCCP` chunk into its name and decompressed contents
+fn split_compressed(data: &[u8]) -> Result<(&[u8], Vec<u8>)>
+{
+    let separator = data.iter().position(|&byte| byte == 0).context("Missing name separator")?;
+    match data.get(separator + 1)
+    {
+        Some(0) => Ok((&data[..separator], decompress(&data[separator + 2..])?)),
+        Some(method) => bail!("Unknown compression method {method}"),
+        None => bail!("Missing compression method"),
+    }
+}
+
+
+/// `name`, its separator, compression method 0 and the compressed `contents`
+fn join_compressed(name: &str, contents: &[u8]) -> Result<Vec<u8>>
+{
+    let mut data = name.as_bytes().to_vec();
+    data.extend_from_slice(&[0, 0]);
+    data.extend(compress(contents)?);
+    Ok(data)
+}
+
+
+/// The payload preceded by its length and zero padded to a multiple of `align`
+fn frame(payload: &[u8], align: usize) -> Result<Vec<u8>>
+{
+    let length = u32::try_from(payload.len()).context("The message is too long")?;
+
+    let mut framed = length.to_be_bytes().to_vec();
+    framed.extend_from_slice(payload);
+    framed.resize((framed.len() + align - 1) / align * align, 0);
+    Ok(framed)
+}
+
+
+fn unframe(framed: &[u8]) -> Result<Vec<u8>>
+{
+    let (length, payload) = match framed
+    {
+        [a, b, c, d, payload @ ..] => (u32::from_be_bytes([*a, *b, *c, *d]) as usize, payload),
+        _ => bail!("No hidden message"),
+    };
+
+    match payload.get(..length)
+    {
+        Some(payload) => Ok(payload.to_vec()),
+        None => bail!("No hidden message"),
+    }
+}
+
+
+/// A raw profile in the format of ImageMagick: its name, length and hex lines
+fn text_contents(payload: &[u8]) -> Vec<u8>
+{
+    let digits = hex::encode(payload);
+
+    let mut text = format!("\niptc\n{:8}\n", payload.len());
+    for line in digits.as_bytes().chunks(HEX_LINE_LENGTH)
+    {
+        text.push_str(std::str::from_utf8(line).unwrap());
+        text.push('\n');
+    }
+
+    text.into_bytes()
+}
+
+
+fn text_payload(contents: &[u8]) -> Result<Vec<u8>>
+{
+    let text = std::str::from_utf8(contents).context("The raw profile is not text")?;
+    let mut lines = text.lines().filter(|line| !line.is_empty()).skip(1);
+
+    let length: usize = lines.next()
+        .and_then(|line| line.trim().parse().ok())
+        .context("The raw profile has no length")?;
+    let digits: String = lines.map(str::trim).collect();
+
+    let payload = hex::decode(digits).context("The raw profile is not hex")?;
+    if payload.len() != length
+    {
+        bail!("The raw profile holds {} bytes instead of {length}", payload.len());
+    }
+
+    Ok(payload)
+}
+
+
+/// Big-endian s15Fixed16Number values of an ICC profile
+fn fixed(values: &[f64]) -> Vec<u8>
+{
+    values.iter().flat_map(|value| ((value * 65536.0).round() as i32).to_be_bytes()).collect()
+}
+
+
+/// An ICC tag element of `tag_type` holding `contents`
+fn element(tag_type: &[u8; 4], contents: &[u8]) -> Vec<u8>
+{
+    [&tag_type[..], &[0; 4], contents].concat()
+}
+
+
+/// An `mluc` element with a single English `text`
+fn text_element(text: &str) -> Vec<u8>
+{
+    let text: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    let record = [&1u32.to_be_bytes()[..], &12u32.to_be_bytes(), b"enUS", &(text.len() as u32).to_be_bytes(), &28u32.to_be_bytes()];
+    element(b"mluc", &[&record.concat()[..], &text].concat())
+}
+
+
+/// An ICC v4 display profile equivalent to `sRGB`, with the framed payload as padding after the tag data
+fn profile_contents(payload: &[u8]) -> Result<Vec<u8>>
+{
+    // Primaries adapted to D50 and the sRGB transfer function, as in the built-in sRGB profile of lcms
+    let curve = [&[0, 3, 0, 0][..], &fixed(&[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045])].concat();
+    let tags: [(&[&[u8; 4]], Vec<u8>); 8] = [
+        (&[b"desc"], text_element("sRGB")),
+        (&[b"cprt"], text_element("No copyright, use freely")),
+        (&[b"wtpt"], element(b"XYZ ", &fixed(&[0.9642, 1.0, 0.8249]))),
+        (&[b"chad"], element(b"sf32", &fixed(&[1.047882, 0.022918, -0.050217, 0.029586, 0.990478, -0.017075, -0.009232, 0.015075, 0.751678]))),
+        (&[b"rXYZ"], element(b"XYZ ", &fixed(&[0.436066, 0.222488, 0.013916]))),
+        (&[b"gXYZ"], element(b"XYZ ", &fixed(&[0.385147, 0.716873, 0.097076]))),
+        (&[b"bXYZ"], element(b"XYZ ", &fixed(&[0.143066, 0.060608, 0.714096]))),
+        (&[b"rTRC", b"gTRC", b"bTRC"], element(b"para", &curve)),
+    ];
+
+    let mut profile = vec![0; PROFILE_HEADER_LENGTH];
+    profile[4..8].copy_from_slice(b"lcms");
+    profile[8..12].copy_from_slice(&[4, 0x30, 0, 0]);
+    profile[12..16].copy_from_slice(b"mntr");
+    profile[16..20].copy_from_slice(b"RGB ");
+    profile[20..24].copy_from_slice(b"XYZ ");
+    for (field, value) in [2024u16, 1, 1, 0, 0, 0].into_iter().enumerate()
+    {
+        profile[24 + field * 2..26 + field * 2].copy_from_slice(&value.to_be_bytes());
+    }
+    profile[36..40].copy_from_slice(b"acsp");
+    profile[40..44].copy_from_slice(b"APPL");
+    profile[68..80].copy_from_slice(&fixed(&[0.9642, 1.0, 0.8249]));
+    profile[80..84].copy_from_slice(b"lcms");
+
+    let count: usize = tags.iter().map(|(signatures, _)| signatures.len()).sum();
+    let table_end = PROFILE_HEADER_LENGTH + 4 + count * PROFILE_TAG_LENGTH;
+    profile.extend((count as u32).to_be_bytes());
+
+    let mut elements = vec![];
+    for (signatures, element) in tags
+    {
+        for signature in signatures
+        {
+            profile.extend_from_slice(&signature[..]);
+            profile.extend(((table_end + elements.len()) as u32).to_be_bytes());
+            profile.extend((element.len() as u32).to_be_bytes());
+        }
+        elements.extend(element);
+        elements.resize((elements.len() + 3) / 4 * 4, 0);
+    }
+
+    profile.extend(elements);
+    profile.extend(frame(payload, 4)?);
+    let size = profile.len() as u32;
+    profile[..4].copy_from_slice(&size.to_be_bytes());
+
+    Ok(profile)
+}
+
+
+fn profile_payload(profile: &[u8]) -> Result<Vec<u8>>
+{
+    if profile.get(36..40) != Some(b"acsp")
+    {
+        bail!("Not an ICC profile");
+    }
+
+    let field = |offset: usize| profile.get(offset..offset + 4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()) as usize);
+    let size = field(0).context("Truncated ICC profile")?;
+    let count = field(PROFILE_HEADER_LENGTH).context("Truncated ICC profile")?;
+    if size < PROFILE_HEADER_LENGTH + 4 || size > profile.len() || count > (size - PROFILE_HEADER_LENGTH - 4) / PROFILE_TAG_LENGTH
+    {
+        bail!("Invalid ICC profile size");
+    }
+
+    // The padding starts after the tag table and the last tag element
+    let table_end = PROFILE_HEADER_LENGTH + 4 + count * PROFILE_TAG_LENGTH;
+    let data_end = (0..count)
+        .map(|tag| PROFILE_HEADER_LENGTH + 4 + tag * PROFILE_TAG_LENGTH)
+        .map(|entry| field(entry + 4).unwrap().saturating_add(field(entry + 8).unwrap()))
+        .fold(table_end, usize::max);
+
+    match profile.get(data_end.saturating_add(3) / 4 * 4..size)
+    {
+        Some(padding) => unframe(padding),
+        None => bail!("The ICC profile holds no hidden message"),
+    }
+}
+
+
+/// The first chunk of `png` of the carrier's type and name
+fn find(png: &Png, carrier: Carrier) -> Option<&Chunk>
+{
+    let name = match carrier
+    {
+        Carrier::Text => TEXT_KEYWORD,
+        Carrier::Profile => PROFILE_NAME,
+        Carrier::Palette => PALETTE_NAME,
+    };
+
+    png.chunks_by_type(carrier.chunk_type()).find(|chunk| {
+        let data = chunk.data();
+        data.len() > name.len() && data.starts_with(name.as_bytes()) && data[name.len()] == 0
+    })
+}
+
+
+/// Index of the first chunk of one of `chunk_types`, or of `IEND`
+fn position(png: &Png, chunk_types: &[&str]) -> usize
+{
+    png.chunks().iter()
+        .position(|chunk| chunk_types.contains(&chunk.chunk_type().to_string().as_str()))
+        .unwrap_or_else(|| png.chunks().len().saturating_sub(1))
+}
+
+
+/// Stores `payload` in a new chunk of `carrier`, placed where the PNG specification expects it.
+/// An `iCCP` carrier holds an sRGB profile and replaces the `sRGB` chunk, which may not appear
+/// alongside it. Fails if the PNG already has a disguised message in the carrier, an ICC profile,
+/// or `gAMA` or `cHRM` describing another color space
+pub fn hide(png: &mut Png, carrier: Carrier, payload: &[u8]) -> Result<()>
+{
+    if find(png, carrier).is_some()
+    {
+        bail!("The PNG already has a disguised {carrier} message");
+    }
+
+    let (data, index) = match carrier
+    {
+        Carrier::Text => (join_compressed(TEXT_KEYWORD, &text_contents(payload))?, position(png, &["IEND"])),
+        Carrier::Profile => {
+            if png.chunk_by_type("iCCP").is_some()
+            {
+                bail!("The PNG already has an ICC profile");
+            }
+            if png.chunk_by_type("sRGB").is_none() && (png.chunk_by_type("gAMA").is_some() || png.chunk_by_type("cHRM").is_some())
+            {
+                bail!("The PNG has gAMA or cHRM, which an sRGB profile would override");
+            }
+            while png.remove_chunk("sRGB").is_ok() {}
+            (join_compressed(PROFILE_NAME, &profile_contents(payload)?)?, position(png, &["PLTE", "IDAT"]))
+        }
+        Carrier::Palette => {
+            let mut data = PALETTE_NAME.as_bytes().to_vec();
+            data.extend_from_slice(&[0, 8]);
+            data.extend(frame(payload, PALETTE_ENTRY_LENGTH)?);
+            (data, position(png, &["IDAT"]))
+        }
+    };
+
+    png.insert_chunk(index, Chunk::new(ChunkType::from_str(carrier.chunk_type())?, data));
+    Ok(())
+}
+
+
+/// Retrieves the payload stored by `hide` in a chunk of `carrier`
+pub fn reveal(png: &Png, carrier: Carrier) -> Result<Vec<u8>>
+{
+    let chunk = find(png, carrier).with_context(|| format!("No disguised {carrier} chunk found"))?;
+
+    match carrier
+    {
+        Carrier::Text => text_payload(&split_compressed(chunk.data())?.1),
+        Carrier::Profile => profile_payload(&split_compressed(chunk.data())?.1),
+        Carrier::Palette => {
+            let entries = &chunk.data()[PALETTE_NAME.len() + 1..];
+            if entries.first() != Some(&8)
+            {
+                bail!("The suggested palette holds no hidden message");
+            }
+            unframe(&entries[1..])
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_hide_reveal() {
+        for carrier in Carrier::ALL {
+            for payload in [&b""[..], b"x", b"a hidden message of some length", &[0xFF; 200]] {
+                let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+                hide(&mut png, carrier, payload).unwrap();
+
+                let png = Png::try_from(&png.as_bytes()[..]).unwrap();
+                assert_eq!(reveal(&png, carrier).unwrap(), payload, "{carrier}");
+                assert!(png.chunks().iter().all(Chunk::is_crc_valid));
+            }
+        }
+    }
+
+    #[test]
+    fn test_placement() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        for carrier in Carrier::ALL {
+            hide(&mut png, carrier, b"message").unwrap();
+        }
+
+        let types = chunk_types(&png);
+        let idat = types.iter().position(|t| t == "IDAT").unwrap();
+        assert!(types.iter().position(|t| t == "iCCP").unwrap() < idat);
+        assert!(types.iter().position(|t| t == "sPLT").unwrap() < idat);
+        assert_eq!(types[types.len() - 2], "zTXt");
+        assert!(!types.contains(&String::from("sRGB")));
+
+        for carrier in Carrier::ALL {
+            assert!(hide(&mut png, carrier, b"again").is_err());
+        }
+    }
+
+    #[test]
+    fn test_profile_format() {
+        let profile = profile_contents(b"message").unwrap();
+        let field = |offset: usize| u32::from_be_bytes(profile[offset..offset + 4].try_into().unwrap()) as usize;
+        assert_eq!(field(0), profile.len());
+
+        let tags: Vec<_> = (0..field(128)).map(|tag| 132 + tag * 12).collect();
+        let signatures: Vec<_> = tags.iter().map(|&entry| &profile[entry..entry + 4]).collect();
+        for required in [b"desc", b"cprt", b"wtpt", b"rXYZ", b"gXYZ", b"bXYZ", b"rTRC", b"gTRC", b"bTRC"] {
+            assert!(signatures.contains(&&required[..]));
+        }
+        for entry in tags {
+            assert_eq!(field(entry + 4) % 4, 0);
+            assert!(field(entry + 4) + field(entry + 8) <= profile.len() - 12);
+        }
+
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.remove_chunk("sRGB").unwrap();
+        png.insert_chunk(1, Chunk::new(ChunkType::from_str("gAMA").unwrap(), 45455u32.to_be_bytes().to_vec()));
+        assert!(hide(&mut png, Carrier::Profile, b"message").is_err());
+    }
+
+    #[test]
+    fn test_text_format() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        hide(&mut png, Carrier::Text, &[0xAB; 40]).unwrap();
+
+        let (keyword, text) = split_compressed(png.chunk_by_type("zTXt").unwrap().data()).unwrap();
+        assert_eq!(keyword, TEXT_KEYWORD.as_bytes());
+        let text = String::from_utf8(text).unwrap();
+        assert_eq!(text.lines().collect::<Vec<_>>(), ["", "iptc", "      40", &"ab".repeat(36), &"ab".repeat(4)]);
+    }
+
+    #[test]
+    fn test_reveal_missing() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        for carrier in Carrier::ALL {
+            assert!(reveal(&png, carrier).is_err());
+        }
+
+        png.append_chunk(Chunk::new(ChunkType::from_str("zTXt").unwrap(), join_compressed("Comment", b"hi").unwrap()));
+        assert!(reveal(&png, Carrier::Text).is_err());
+        let bomb = join_compressed(TEXT_KEYWORD, &vec![b'0'; 1 << 20]).unwrap();
+        assert!(split_compressed(&bomb).unwrap_err().to_string().contains("inflates to more than"));
+
+        // Profile sizes smaller than the header, or larger than the data
+        for size in [0u32, 16, 131, u32::MAX] {
+            let mut profile = profile_contents(b"x").unwrap();
+            profile[..4].copy_from_slice(&size.to_be_bytes());
+            let iccp = Chunk::new(ChunkType::from_str("iCCP").unwrap(), join_compressed(PROFILE_NAME, &profile).unwrap());
+            let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+            png.insert_chunk(1, iccp);
+            assert!(reveal(&png, Carrier::Profile).is_err());
+        }
+
+        assert!("ruSt".parse::<Carrier>().is_err());
+        assert_eq!("iCCP".parse::<Carrier>().unwrap(), Carrier::Profile);
+    }
+}
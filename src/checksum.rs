@@ -0,0 +1,253 @@
+/// A running CRC-32 as used by PNG (ISO 3309, reflected polynomial `0xedb88320`)
+pub trait Crc32: Default
+{
+    fn update(&mut self, bytes: &[u8]);
+
+    fn finish(&self) -> u32;
+
+    /// The CRC of `parts` concatenated, without copying them
+    fn checksum(parts: &[&[u8]]) -> u32
+    {
+        let mut crc = Self::default();
+        for part in parts
+        {
+            crc.update(part);
+        }
+        crc.finish()
+    }
+}
+
+
+/// The implementation used for chunk CRCs, selected by the `crc-simd` and `crc-portable` features.
+/// Without either, the slice-by-8 table is used
+#[cfg(feature = "crc-simd")]
+pub type Backend = Simd;
+
+#[cfg(all(not(feature = "crc-simd"), feature = "crc-portable"))]
+pub type Backend = Portable;
+
+#[cfg(not(any(feature = "crc-simd", feature = "crc-portable")))]
+pub type Backend = SliceBy8;
+
+
+const POLYNOMIAL: u32 = 0xedb88320;
+
+/// `TABLES[k][b]` is the CRC of byte `b` followed by `k` zero bytes
+static TABLES: [[u32; 256]; 8] = make_tables();
+
+
+const fn make_tables() -> [[u32; 256]; 8]
+{
+    let mut tables = [[0; 256]; 8];
+
+    let mut i = 0;
+    while i < 256
+    {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8
+        {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLYNOMIAL } else { crc >> 1 };
+            bit += 1;
+        }
+        tables[0][i] = crc;
+        i += 1;
+    }
+
+    let mut k = 1;
+    while k < 8
+    {
+        let mut i = 0;
+        while i < 256
+        {
+            let previous = tables[k - 1][i];
+            tables[k][i] = (previous >> 8) ^ tables[0][(previous & 0xff) as usize];
+            i += 1;
+        }
+        k += 1;
+    }
+
+    tables
+}
+
+
+fn update_bytewise(mut crc: u32, bytes: &[u8]) -> u32
+{
+    for &byte in bytes
+    {
+        crc = TABLES[0][((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+
+/// One table lookup per byte, the classic implementation from the PNG specification
+#[derive(Debug, Clone, Copy)]
+pub struct Portable
+{
+    state: u32,
+}
+
+
+impl Default for Portable
+{
+    fn default() -> Self
+    {
+        Self {state: !0}
+    }
+}
+
+
+impl Crc32 for Portable
+{
+    fn update(&mut self, bytes: &[u8])
+    {
+        self.state = update_bytewise(self.state, bytes);
+    }
+
+    fn finish(&self) -> u32
+    {
+        !self.state
+    }
+}
+
+
+/// Eight table lookups per 8 bytes, without data dependent branches
+#[derive(Debug, Clone, Copy)]
+pub struct SliceBy8
+{
+    state: u32,
+}
+
+
+impl Default for SliceBy8
+{
+    fn default() -> Self
+    {
+        Self {state: !0}
+    }
+}
+
+
+impl Crc32 for SliceBy8
+{
+    fn update(&mut self, bytes: &[u8])
+    {
+        let mut crc = self.state;
+        let mut blocks = bytes.chunks_exact(8);
+
+        for block in &mut blocks
+        {
+            let low = u32::from_le_bytes([block[0], block[1], block[2], block[3]]) ^ crc;
+            let high = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+
+            crc = TABLES[7][(low & 0xff) as usize]
+                ^ TABLES[6][((low >> 8) & 0xff) as usize]
+                ^ TABLES[5][((low >> 16) & 0xff) as usize]
+                ^ TABLES[4][(low >> 24) as usize]
+                ^ TABLES[3][(high & 0xff) as usize]
+                ^ TABLES[2][((high >> 8) & 0xff) as usize]
+                ^ TABLES[1][((high >> 16) & 0xff) as usize]
+                ^ TABLES[0][(high >> 24) as usize];
+        }
+
+        self.state = update_bytewise(crc, blocks.remainder());
+    }
+
+    fn finish(&self) -> u32
+    {
+        !self.state
+    }
+}
+
+
+/// Carry-less multiplication (PCLMULQDQ, or PMULL on aarch64) when the CPU supports it,
+/// with a table driven fallback. Support is detected at runtime with `std`, at compile time without
+#[cfg(feature = "crc-simd")]
+#[derive(Clone, Default)]
+pub struct Simd
+{
+    hasher: crc32fast::Hasher,
+}
+
+
+#[cfg(feature = "crc-simd")]
+impl Crc32 for Simd
+{
+    fn update(&mut self, bytes: &[u8])
+    {
+        self.hasher.update(bytes);
+    }
+
+    fn finish(&self) -> u32
+    {
+        self.hasher.clone().finalize()
+    }
+}
+
+
+#[cfg(feature = "crc-simd")]
+impl core::fmt::Debug for Simd
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+    {
+        f.debug_struct("Simd").field("crc", &self.finish()).finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_bytes(length: usize) -> Vec<u8> {
+        let mut state: u32 = 0x9e3779b9;
+        (0..length).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }).collect()
+    }
+
+    fn assert_matches_reference<C: Crc32>() {
+        let bytes = testing_bytes(4099);
+
+        // Every length around the 8 byte blocks, and every alignment of the start
+        for start in 0..16 {
+            for end in (start..80).chain([1000, 4096, 4099]) {
+                let expected = crc::crc32::checksum_ieee(&bytes[start..end]);
+                assert_eq!(C::checksum(&[&bytes[start..end]]), expected, "bytes {start}..{end}");
+            }
+        }
+
+        // Split updates must not depend on where the split happens
+        let expected = crc::crc32::checksum_ieee(&bytes);
+        for split in [0, 1, 7, 8, 9, 100, 4098, 4099] {
+            assert_eq!(C::checksum(&[&bytes[..split], &bytes[split..]]), expected, "split at {split}");
+        }
+    }
+
+    #[test]
+    fn test_known_values() {
+        assert_eq!(Backend::checksum(&[]), 0);
+        assert_eq!(Backend::checksum(&[b"123456789"]), 0xcbf43926);
+        assert_eq!(Backend::checksum(&[b"IEND"]), 0xae426082);
+    }
+
+    #[test]
+    fn test_portable() {
+        assert_matches_reference::<Portable>();
+    }
+
+    #[test]
+    fn test_slice_by_8() {
+        assert_matches_reference::<SliceBy8>();
+    }
+
+    #[cfg(feature = "crc-simd")]
+    #[test]
+    fn test_simd() {
+        assert_matches_reference::<Simd>();
+    }
+}
@@ -1,21 +1,119 @@
+use crate::chunk_ref::ChunkRef;
 use crate::chunk_type::ChunkType;
-use anyhow::{Result, Error, bail};
-use std::fmt;
-
-
+use crate::checksum::{self, Crc32};
+use crate::error::{Error, Result};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+
+/// An owned chunk: its type, data and CRC
+///
+/// ```
+/// use pngme::chunk::Chunk;
+/// use pngme::chunk_type::ChunkType;
+///
+/// let chunk = Chunk::new(ChunkType::new(*b"ruSt")?, b"secret".to_vec());
+/// assert_eq!(chunk.data(), b"secret");
+/// assert_eq!(chunk.as_bytes().len(), 6 + Chunk::OVERHEAD);
+/// # Ok::<(), pngme::error::Error>(())
+/// ```
 #[derive(Debug, Clone)]
 pub struct Chunk
 {
     chunk_type: ChunkType,
     data: Vec<u8>,
+    crc: u32,
+    stored_crc: u32,
+}
+
+
+/// Computes the CRC over the chunk type followed by the data, without concatenating them
+pub(crate) fn checksum(chunk_type: &ChunkType, data: &[u8]) -> u32
+{
+    checksum::Backend::checksum(&[&chunk_type.bytes(), data])
+}
+
+
+/// Size of the buffer that streaming readers check skipped chunk data through
+#[cfg(feature = "std")]
+pub(crate) const SKIP_BUFFER: usize = 8192;
+
+
+/// Parses the length and type that start a chunk, refusing lengths above `max_length`
+#[cfg(feature = "std")]
+pub(crate) fn parse_head(head: &[u8; 8], max_length: usize) -> Result<(usize, ChunkType)>
+{
+    let length = u32::from_be_bytes([head[0], head[1], head[2], head[3]]) as usize;
+    let chunk_type = ChunkType::new([head[4], head[5], head[6], head[7]])?;
+    if length > max_length
+    {
+        crate::error::bail!("{chunk_type} chunk of {length} bytes exceeds the limit of {max_length} bytes");
+    }
+
+    Ok((length, chunk_type))
+}
+
+
+/// The CRC of a chunk whose data is read in pieces, for checking it without keeping the data
+#[cfg(feature = "std")]
+pub(crate) struct StreamingCrc
+{
+    chunk_type: ChunkType,
+    crc: checksum::Backend,
+}
+
+
+#[cfg(feature = "std")]
+impl StreamingCrc
+{
+    pub(crate) fn new(chunk_type: ChunkType) -> Self
+    {
+        let mut crc = checksum::Backend::default();
+        crc.update(&chunk_type.bytes());
+        Self {chunk_type, crc}
+    }
+
+    pub(crate) fn update(&mut self, piece: &[u8])
+    {
+        self.crc.update(piece);
+    }
+
+    /// Compares the CRC of the pieces read with the `stored` CRC that follows them
+    pub(crate) fn verify(self, stored: [u8; 4]) -> Result<()>
+    {
+        let (stored, computed) = (u32::from_be_bytes(stored), self.crc.finish());
+        if stored != computed
+        {
+            return Err(Error::InvalidCrc {chunk_type: self.chunk_type, stored, computed});
+        }
+
+        Ok(())
+    }
 }
 
 
 impl Chunk
 {
+    /// Length, type and CRC fields around the data
+    pub const OVERHEAD: usize = 12;
+    /// Largest chunk length allowed by the PNG specification
+    pub const MAX_LENGTH: usize = (1 << 31) - 1;
+
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Self
     {
-        Chunk {chunk_type, data}
+        let crc = checksum(&chunk_type, &data);
+        Chunk {chunk_type, data, crc, stored_crc: crc}
+    }
+
+    /// Creates a parsed chunk from its already computed CRC and the CRC stored in the file
+    pub(crate) fn from_parts(chunk_type: ChunkType, data: Vec<u8>, crc: u32, stored_crc: u32) -> Self
+    {
+        Chunk {chunk_type, data, crc, stored_crc}
     }
 
     pub fn length(&self) -> u32
@@ -28,50 +126,71 @@ impl Chunk
         &self.chunk_type
     }
 
-    fn data(&self) -> &[u8]
+    pub fn data(&self) -> &[u8]
     {
         &self.data
     }
 
+    /// The CRC of the chunk type and data
     pub fn crc(&self) -> u32
     {
-        let type_and_data: Vec<u8> = self.chunk_type()
-            .bytes()
-            .iter()
-            .cloned()
-            .chain(self.data()
-                .iter()
-                .cloned())
-            .collect();
+        self.crc
+    }
 
-        crc::crc32::checksum_ieee(&type_and_data[..])
+    /// The CRC read from the file, which is written back unless repaired
+    pub fn stored_crc(&self) -> u32
+    {
+        self.stored_crc
+    }
+
+    pub fn is_crc_valid(&self) -> bool
+    {
+        self.crc == self.stored_crc
+    }
+
+    /// Replaces a wrong stored CRC with the computed one. Returns true if it was wrong
+    pub fn repair_crc(&mut self) -> bool
+    {
+        let repaired = !self.is_crc_valid();
+        self.stored_crc = self.crc;
+        repaired
     }
 
     pub fn data_as_string(&self) -> Result<String>
     {
-        Ok(String::from_utf8(self.data.clone())?)
+        String::from_utf8(self.data.clone()).map_err(|_| Error::InvalidUtf8)
     }
 
-    pub fn as_bytes(&self) -> Vec<u8>
+    /// Number of bytes the serialized chunk takes
+    pub fn byte_len(&self) -> usize
     {
-        let result: Vec<u8> = self.length()
-            .to_be_bytes()
-            .iter()
-            .cloned()
-            .chain(self.chunk_type
-                .bytes()
-                .iter()
-                .cloned())
-            .chain(self.data
-                .iter()
-                .cloned())
-            .chain(self.crc()
-                .to_be_bytes()
-                .iter()
-                .cloned())
-            .collect();
+        self.data.len() + Self::OVERHEAD
+    }
 
-        result
+    /// Writes the serialized chunk: length, type, data and stored CRC
+    #[cfg(feature = "std")]
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()>
+    {
+        writer.write_all(&self.length().to_be_bytes())?;
+        writer.write_all(&self.chunk_type.bytes())?;
+        writer.write_all(&self.data)?;
+        writer.write_all(&self.stored_crc.to_be_bytes())
+    }
+
+    /// Appends the serialized chunk, like `write_to` without `std::io`
+    pub(crate) fn extend_bytes(&self, bytes: &mut Vec<u8>)
+    {
+        bytes.extend_from_slice(&self.length().to_be_bytes());
+        bytes.extend_from_slice(&self.chunk_type.bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes.extend_from_slice(&self.stored_crc.to_be_bytes());
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8>
+    {
+        let mut bytes = Vec::with_capacity(self.byte_len());
+        self.extend_bytes(&mut bytes);
+        bytes
     }
 }
 
@@ -82,30 +201,7 @@ impl TryFrom<&[u8]> for Chunk
 
     fn try_from(bytes: &[u8]) -> Result<Self>
     {
-        let length_array: [u8; 4] = bytes[0..4].try_into()?;
-        let chunk_type_array: [u8; 4] = bytes[4..8].try_into()?;
-        let data_array: &[u8] = &bytes[8..bytes.len() - 4];
-        let crc_array: [u8; 4] = bytes[bytes.len() - 4..bytes.len()].try_into()?;
-
-        let length = u32::from_be_bytes(length_array);
-        let chunk_type: ChunkType = ChunkType::try_from(chunk_type_array)?;
-        let data_vec: Vec<u8> = data_array.to_vec();
-        let crc = u32::from_be_bytes(crc_array);
-
-        let data_length = data_vec.len() as u32;
-        if length != data_length
-        {
-            bail!("Length mismatch {length} != {data_length}")
-        }
-
-        let new_chunk = Self::new(chunk_type, data_vec);
-
-        if crc != new_chunk.crc()
-        {
-            bail!("Invalid CRC")
-        }
-
-        Ok(new_chunk)
+        Ok(ChunkRef::try_from(bytes)?.to_chunk())
     }
 }
 
@@ -119,6 +215,10 @@ impl fmt::Display for Chunk
         writeln!(f, "  Type: {}", self.chunk_type())?;
         writeln!(f, "  Data: {} bytes", self.data().len())?;
         writeln!(f, "  Crc: {}", self.crc())?;
+        if !self.is_crc_valid()
+        {
+            writeln!(f, "  Stored crc: {} (invalid)", self.stored_crc())?;
+        }
         writeln!(f, "}}",)?;
         Ok(())
     }
@@ -223,6 +323,50 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_chunk_write_to() {
+        let chunk = testing_chunk();
+        let mut bytes = vec![];
+        chunk.write_to(&mut bytes).unwrap();
+
+        assert_eq!(bytes, chunk.as_bytes());
+        assert_eq!(bytes.len(), chunk.byte_len());
+        assert_eq!(&bytes[bytes.len() - 4..], &2882656334u32.to_be_bytes());
+    }
+
+    #[test]
+    fn test_stored_crc() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.stored_crc(), 2882656334);
+        assert!(chunk.is_crc_valid());
+
+        let mut bytes = chunk.as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        let mut chunk = ChunkRef::try_from_unchecked(&bytes[..]).unwrap().to_chunk();
+        assert!(!chunk.is_crc_valid());
+        assert_eq!(chunk.stored_crc(), 2882656334 ^ 1);
+        assert_eq!(chunk.as_bytes(), bytes);
+        assert!(chunk.to_string().contains("(invalid)"));
+
+        assert!(chunk.repair_crc());
+        assert!(chunk.is_crc_valid());
+        assert!(!chunk.repair_crc());
+        assert_eq!(chunk.as_bytes(), testing_chunk().as_bytes());
+    }
+
+    #[test]
+    fn test_checksum() {
+        let chunk_type = ChunkType::try_from(*b"RuSt").unwrap();
+        let data = b"This is where your secret message will be!";
+        let type_and_data: Vec<u8> = b"RuSt".iter().chain(data).copied().collect();
+
+        assert_eq!(checksum(&chunk_type, data), crc::crc32::checksum_ieee(&type_and_data));
+        assert_eq!(Chunk::new(chunk_type, data.to_vec()).crc(), 2882656334);
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
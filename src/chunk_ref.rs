@@ -0,0 +1,219 @@
+use crate::chunk::{self, Chunk};
+use crate::chunk_type::ChunkType;
+
+use crate::error::{Error, Result, bail};
+
+use core::fmt;
+
+
+/// A chunk borrowed from the underlying PNG bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkRef<'a>
+{
+    chunk_type: ChunkType,
+    data: &'a [u8],
+    crc: u32,
+    stored_crc: u32,
+}
+
+
+impl<'a> ChunkRef<'a>
+{
+    /// Length, type and CRC fields around the data
+    pub const OVERHEAD: usize = Chunk::OVERHEAD;
+
+    pub fn length(&self) -> u32
+    {
+        self.data.len() as u32
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType
+    {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &'a [u8]
+    {
+        self.data
+    }
+
+    /// The CRC of the chunk type and data, computed without copying them
+    pub fn crc(&self) -> u32
+    {
+        self.crc
+    }
+
+    /// The CRC read from the file
+    pub fn stored_crc(&self) -> u32
+    {
+        self.stored_crc
+    }
+
+    pub fn data_as_str(&self) -> Result<&'a str>
+    {
+        core::str::from_utf8(self.data).map_err(|_| Error::InvalidUtf8)
+    }
+
+    pub fn is_crc_valid(&self) -> bool
+    {
+        self.crc == self.stored_crc
+    }
+
+    /// Copies the chunk data into an owned chunk, keeping the stored CRC
+    pub fn to_chunk(&self) -> Chunk
+    {
+        Chunk::from_parts(self.chunk_type.clone(), self.data.to_vec(), self.crc, self.stored_crc)
+    }
+
+    /// Parses the chunk at the start of `bytes` and returns it with the number of bytes it spans
+    pub fn parse(bytes: &'a [u8]) -> Result<(Self, usize)>
+    {
+        let (chunk, end) = Self::parse_unchecked(bytes)?;
+        chunk.verify_crc()?;
+
+        Ok((chunk, end))
+    }
+
+    /// Like `parse`, but accepts a chunk whose stored CRC does not match its contents
+    pub fn parse_unchecked(bytes: &'a [u8]) -> Result<(Self, usize)>
+    {
+        if bytes.len() < Self::OVERHEAD
+        {
+            bail!("Unexpected end of chunk: {} bytes left", bytes.len())
+        }
+
+        let length = u32::from_be_bytes(bytes[0..4].try_into()?) as usize;
+        let end = match length.checked_add(Self::OVERHEAD)
+        {
+            Some(end) if end <= bytes.len() => end,
+            _ => bail!("Length mismatch {length} > {}", bytes.len() - Self::OVERHEAD),
+        };
+
+        let chunk = Self::try_from_unchecked(&bytes[..end])?;
+
+        Ok((chunk, end))
+    }
+
+    /// Like `try_from`, but accepts a chunk whose stored CRC does not match its contents
+    pub fn try_from_unchecked(bytes: &'a [u8]) -> Result<Self>
+    {
+        if bytes.len() < Self::OVERHEAD
+        {
+            bail!("Chunk too short: {} bytes", bytes.len())
+        }
+
+        let length_array: [u8; 4] = bytes[0..4].try_into()?;
+        let chunk_type_array: [u8; 4] = bytes[4..8].try_into()?;
+        let data: &[u8] = &bytes[8..bytes.len() - 4];
+        let crc_array: [u8; 4] = bytes[bytes.len() - 4..bytes.len()].try_into()?;
+
+        let length = u32::from_be_bytes(length_array);
+        let chunk_type = ChunkType::try_from(chunk_type_array)?;
+        let stored_crc = u32::from_be_bytes(crc_array);
+
+        let data_length = data.len() as u32;
+        if length != data_length
+        {
+            bail!("Length mismatch {length} != {data_length}")
+        }
+
+        let crc = chunk::checksum(&chunk_type, data);
+
+        Ok(Self {chunk_type, data, crc, stored_crc})
+    }
+
+    fn verify_crc(&self) -> Result<()>
+    {
+        if !self.is_crc_valid()
+        {
+            return Err(Error::InvalidCrc {chunk_type: self.chunk_type.clone(), stored: self.stored_crc, computed: self.crc});
+        }
+
+        Ok(())
+    }
+}
+
+
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a>
+{
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self>
+    {
+        let chunk = Self::try_from_unchecked(bytes)?;
+        chunk.verify_crc()?;
+
+        Ok(chunk)
+    }
+}
+
+
+impl fmt::Display for ChunkRef<'_>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        writeln!(f, "Chunk {{",)?;
+        writeln!(f, "  Length: {}", self.length())?;
+        writeln!(f, "  Type: {}", self.chunk_type())?;
+        writeln!(f, "  Data: {} bytes", self.data().len())?;
+        writeln!(f, "  Crc: {}", self.crc())?;
+        if !self.is_crc_valid()
+        {
+            writeln!(f, "  Stored crc: {} (invalid)", self.stored_crc())?;
+        }
+        writeln!(f, "}}",)?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"This is where your secret message will be!".to_vec())
+            .as_bytes()
+    }
+
+    #[test]
+    fn test_chunk_ref_from_bytes() {
+        let bytes = testing_bytes();
+        let chunk = ChunkRef::try_from(&bytes[..]).unwrap();
+
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.chunk_type().to_string(), "RuSt");
+        assert_eq!(chunk.data_as_str().unwrap(), "This is where your secret message will be!");
+        assert_eq!(chunk.crc(), 2882656334);
+        assert_eq!(chunk.data().as_ptr(), bytes[8..].as_ptr());
+    }
+
+    #[test]
+    fn test_chunk_ref_invalid_crc() {
+        let mut bytes = testing_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(ChunkRef::try_from(&bytes[..]).is_err());
+
+        let chunk = ChunkRef::try_from_unchecked(&bytes[..]).unwrap();
+        assert!(!chunk.is_crc_valid());
+        assert_eq!(chunk.crc(), 2882656334);
+        assert_eq!(chunk.stored_crc(), 2882656334 ^ 1);
+        assert!(chunk.to_string().contains("Stored crc: 2882656335 (invalid)"));
+        assert_eq!(chunk.to_chunk().as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_chunk_ref_parse() {
+        let mut bytes = testing_bytes();
+        bytes.extend_from_slice(b"rest");
+
+        let (chunk, used) = ChunkRef::parse(&bytes).unwrap();
+        assert_eq!(used, 54);
+        assert_eq!(chunk.to_chunk().as_bytes(), testing_bytes());
+
+        assert!(ChunkRef::parse(&bytes[..20]).is_err());
+        assert!(ChunkRef::parse(&bytes[..4]).is_err());
+    }
+}
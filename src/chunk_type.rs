@@ -1,10 +1,21 @@
-#![allow(dead_code)]
-
-use anyhow::{Result, Error, bail};
-
-use std::str::FromStr;
-use std::fmt;
-
+use crate::error::{Error, Result};
+
+use alloc::string::String;
+
+use core::fmt;
+use core::str::FromStr;
+
+/// The four letter type of a chunk, e.g. `IHDR`
+///
+/// ```
+/// use pngme::chunk_type::ChunkType;
+///
+/// let chunk_type = ChunkType::new(*b"ruSt")?;
+/// assert!(!chunk_type.is_critical());
+/// assert!(!chunk_type.is_public());
+/// assert!(ChunkType::new(*b"ru5t").is_err());
+/// # Ok::<(), pngme::error::Error>(())
+/// ```
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct ChunkType
 {
@@ -14,14 +25,29 @@ pub struct ChunkType
 
 impl ChunkType
 {
-    fn new(data: [u8; 4]) -> Self
+    /// Chunk types defined by the PNG specification and its registered extensions
+    pub const STANDARD: [&'static str; 31] = [
+        "IHDR", "PLTE", "IDAT", "IEND",
+        "tRNS", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "cICP", "mDCv", "cLLi",
+        "tEXt", "zTXt", "iTXt", "bKGD", "hIST", "pHYs", "sPLT", "eXIf", "tIME",
+        "acTL", "fcTL", "fdAT",
+        "oFFs", "pCAL", "sCAL", "gIFg", "gIFx", "sTER",
+    ];
+
+    /// Creates a chunk type, which must consist of ASCII letters
+    pub fn new(data: [u8; 4]) -> Result<Self>
     {
-        Self {data}
+        if !data.iter().all(|&byte| Self::is_valid_byte(byte))
+        {
+            return Err(Error::InvalidChunkType(String::from_utf8_lossy(&data).into_owned()));
+        }
+
+        Ok(Self {data})
     }
 
     pub fn bytes(&self) -> [u8; 4]
     {
-        self.data.clone()
+        self.data
     }
 
     pub fn is_valid_byte(byte: u8) -> bool
@@ -53,6 +79,12 @@ impl ChunkType
     {
         self.data[3].is_ascii_lowercase()
     }
+
+    /// Returns true if the chunk type is a registered PNG chunk type
+    pub fn is_standard(&self) -> bool
+    {
+        Self::STANDARD.iter().any(|s| s.as_bytes() == self.data)
+    }
 }
 
 
@@ -62,7 +94,7 @@ impl TryFrom<[u8; 4]> for ChunkType
 
     fn try_from(bytes: [u8; 4]) -> Result<Self>
     {
-        Ok(Self::new(bytes))
+        Self::new(bytes)
     }
 }
 
@@ -73,17 +105,11 @@ impl FromStr for ChunkType
 
     fn from_str(str: &str) -> Result<Self>
     {
-        let bytes: [u8; 4] = str.as_bytes().try_into()?;
-
-        for i in bytes
+        match str.as_bytes().try_into()
         {
-            if !Self::is_valid_byte(i)
-            {
-                bail!("Invalid byte: {}", i);
-            }
+            Ok(bytes) => Self::new(bytes),
+            Err(_) => Err(Error::InvalidChunkType(String::from(str))),
         }
-
-        Ok(Self::new(bytes))
     }
 }
 
@@ -92,8 +118,7 @@ impl fmt::Display for ChunkType
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
     {
-        let chunk_vec = self.data.to_vec();
-        let chunk_str = String::from_utf8(chunk_vec).expect("Found invalid UTF-8");
+        let chunk_str = core::str::from_utf8(&self.data).expect("Chunk types are ASCII letters");
 
         write!(f, "{chunk_str}")
     }
@@ -169,6 +194,13 @@ mod tests {
         assert!(!chunk.is_safe_to_copy());
     }
 
+    #[test]
+    pub fn test_chunk_type_is_standard() {
+        assert!(ChunkType::from_str("IDAT").unwrap().is_standard());
+        assert!(ChunkType::from_str("tEXt").unwrap().is_standard());
+        assert!(!ChunkType::from_str("RuSt").unwrap().is_standard());
+    }
+
     #[test]
     pub fn test_valid_chunk_is_valid() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -184,6 +216,13 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_new() {
+        assert_eq!(ChunkType::new(*b"RuSt").unwrap(), ChunkType::from_str("RuSt").unwrap());
+        assert!(ChunkType::new(*b"Ru1t").is_err());
+        assert!(ChunkType::try_from([0, 159, 146, 150]).is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
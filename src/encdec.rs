@@ -1,21 +1,31 @@
 
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::{ChaCha20Poly1305, KeyInit, Nonce};
+use chacha20poly1305::aead::Aead;
 use magic_crypt::{new_magic_crypt, MagicCryptTrait};
+use zeroize::Zeroizing;
 
 
+/// Encrypts `message` with AES-256 under a key hashed from `key`. magic-crypt derives that key with
+/// SHA-256 and expands it into round keys and CBC state on every call, none of which it wipes, so only
+/// the caller's copy of `key` can be zeroized. Deniable messages use keys that are wiped
 pub fn encrypt_message(key: &str, message: &str) -> String
 {
-    let mc = new_magic_crypt!(key, 256);
-    let base64 = mc.encrypt_str_to_base64(message);
-
-    base64
+    new_magic_crypt!(key, 256).encrypt_str_to_base64(message)
 }
 
 
+/// Decrypts a message of `encrypt_message` or `encrypt_deniable`
 pub fn decrypt_message(key: &str, base64: &str) -> Result<String>
 {
-    let mc = new_magic_crypt!(key, 256);
-    match mc.decrypt_base64_to_string(&base64)
+    if let Some(message) = decrypt_deniable(key, base64)?
+    {
+        return Ok(message);
+    }
+
+    match new_magic_crypt!(key, 256).decrypt_base64_to_string(base64)
     {
         Ok(message) => Ok(message),
         Err(_) => bail!("Failed to decrypt message. Maybe the key was wrong?")
@@ -23,6 +33,129 @@ pub fn decrypt_message(key: &str, base64: &str) -> Result<String>
 }
 
 
+/// Bytes of the random salt that starts a deniable message
+const SALT_LENGTH: usize = 16;
+
+/// Bytes of the Poly1305 tag that ends each slot
+const TAG_LENGTH: usize = 16;
+
+/// Slot plaintexts are padded to a multiple of this, so that their length says little about the messages
+const PADDING: usize = 64;
+
+/// scrypt cost, 2^15 iterations with 32 MiB of memory
+const SCRYPT_LOG_N: u8 = 15;
+
+
+/// The key of one slot, from the password and the salt of the message
+fn slot_key(key: &str, salt: &[u8]) -> Zeroizing<[u8; 32]>
+{
+    let params = scrypt::Params::new(SCRYPT_LOG_N, 8, 1, 32).expect("Valid scrypt parameters");
+
+    let mut slot_key = Zeroizing::new([0; 32]);
+    scrypt::scrypt(key.as_bytes(), salt, &params, &mut slot_key[..]).expect("Valid scrypt output length");
+    slot_key
+}
+
+
+fn random_bytes(bytes: &mut [u8]) -> Result<()>
+{
+    getrandom::getrandom(bytes).context("Failed to get random bytes")
+}
+
+
+/// Length prefixed message with random padding to `length` bytes, encrypted with the slot key.
+/// Every key is used for a single slot only, so the nonce can be fixed
+fn seal_slot(key: &str, salt: &[u8], message: &str, length: usize) -> Result<Vec<u8>>
+{
+    let mut plaintext = Zeroizing::new(vec![0; length]);
+    plaintext[..4].copy_from_slice(&(message.len() as u32).to_be_bytes());
+    plaintext[4..4 + message.len()].copy_from_slice(message.as_bytes());
+    random_bytes(&mut plaintext[4 + message.len()..])?;
+
+    let cipher = ChaCha20Poly1305::new(slot_key(key, salt).as_ref().into());
+    match cipher.encrypt(&Nonce::default(), &plaintext[..])
+    {
+        Ok(slot) => Ok(slot),
+        Err(_) => bail!("Failed to encrypt message"),
+    }
+}
+
+
+/// Encrypts `message` with `key` into one of two slots of equal size, and `decoy` into the other.
+/// Without a decoy, the other slot is filled with random bytes. The order of the slots is random,
+/// and salt, slots and tags all look random, so no key reveals whether the other slot holds a message
+pub fn encrypt_deniable(key: &str, message: &str, decoy: Option<(&str, &str)>) -> Result<String>
+{
+    if matches!(decoy, Some((decoy_key, _)) if decoy_key == key)
+    {
+        bail!("The decoy key must differ from the key");
+    }
+
+    let longest = message.len().max(decoy.map_or(0, |(_, decoy)| decoy.len()));
+    let length = (4 + longest + PADDING - 1) / PADDING * PADDING;
+
+    let mut salt = [0; SALT_LENGTH];
+    random_bytes(&mut salt)?;
+
+    let real = seal_slot(key, &salt, message, length)?;
+    let other = match decoy
+    {
+        Some((decoy_key, decoy)) => seal_slot(decoy_key, &salt, decoy, length)?,
+        None => {
+            let mut filler = vec![0; length + TAG_LENGTH];
+            random_bytes(&mut filler)?;
+            filler
+        }
+    };
+
+    let mut order = [0];
+    random_bytes(&mut order)?;
+    let (first, second) = if order[0] & 1 == 0 { (real, other) } else { (other, real) };
+
+    let mut bytes = salt.to_vec();
+    bytes.extend_from_slice(&first);
+    bytes.extend_from_slice(&second);
+    Ok(BASE64.encode(bytes))
+}
+
+
+/// Decrypts whichever slot of a message of `encrypt_deniable` opens with `key`. Returns `None` if
+/// the data does not have that layout or no slot opens, which looks the same for a wrong key
+pub fn decrypt_deniable(key: &str, base64: &str) -> Result<Option<String>>
+{
+    let bytes = match BASE64.decode(base64.trim())
+    {
+        Ok(bytes) => bytes,
+        Err(_) => return Ok(None),
+    };
+
+    let slot_length = bytes.len().saturating_sub(SALT_LENGTH) / 2;
+    if bytes.len() != SALT_LENGTH + 2 * slot_length || slot_length < PADDING + TAG_LENGTH
+        || (slot_length - TAG_LENGTH) % PADDING != 0
+    {
+        return Ok(None);
+    }
+
+    let (salt, slots) = bytes.split_at(SALT_LENGTH);
+    let cipher = ChaCha20Poly1305::new(slot_key(key, salt).as_ref().into());
+
+    for slot in slots.chunks_exact(slot_length)
+    {
+        let plaintext = match cipher.decrypt(&Nonce::default(), slot)
+        {
+            Ok(plaintext) => Zeroizing::new(plaintext),
+            Err(_) => continue,
+        };
+
+        let length = u32::from_be_bytes(plaintext[..4].try_into()?) as usize;
+        let message = plaintext.get(4..4 + length).context("Invalid message length in slot")?;
+        return Ok(Some(String::from_utf8(message.to_vec()).context("The message is not valid UTF-8")?));
+    }
+
+    Ok(None)
+}
+
+
 #[cfg(test)]
 mod tests
 {
@@ -48,6 +181,34 @@ mod tests
         assert_eq!(message, "Another secret message!");
     }
 
+    #[test]
+    fn test_deniable() {
+        let encrypted = encrypt_deniable("real key", "The real plan", Some(("decoy key", "Shopping list"))).unwrap();
+        assert_eq!(decrypt_message("real key", &encrypted).unwrap(), "The real plan");
+        assert_eq!(decrypt_message("decoy key", &encrypted).unwrap(), "Shopping list");
+        assert!(decrypt_message("wrong key", &encrypted).is_err());
+
+        // Without a decoy the layout is the same, with random bytes in the other slot
+        let single = encrypt_deniable("real key", "The real plan", None).unwrap();
+        assert_eq!(single.len(), encrypted.len());
+        assert_eq!(decrypt_deniable("real key", &single).unwrap().unwrap(), "The real plan");
+        assert!(decrypt_deniable("decoy key", &single).unwrap().is_none());
+
+        assert!(encrypt_deniable("same", "a", Some(("same", "b"))).is_err());
+    }
+
+    #[test]
+    fn test_deniable_padding() {
+        let short = BASE64.decode(encrypt_deniable("key", "", None).unwrap()).unwrap();
+        assert_eq!(short.len(), SALT_LENGTH + 2 * (PADDING + TAG_LENGTH));
+
+        let long = BASE64.decode(encrypt_deniable("key", &"x".repeat(61), None).unwrap()).unwrap();
+        assert_eq!(long.len(), SALT_LENGTH + 2 * (2 * PADDING + TAG_LENGTH));
+
+        // Messages of `encrypt_message` never have the deniable layout
+        assert!(decrypt_deniable("key", &encrypt_message("key", "message")).unwrap().is_none());
+    }
+
     #[test]
     fn test_encrypt_decrypt()
     {
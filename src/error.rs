@@ -0,0 +1,95 @@
+use crate::chunk_type::ChunkType;
+
+use alloc::string::String;
+
+use core::array::TryFromSliceError;
+use core::fmt;
+
+
+/// Errors of the core parser, available without `std`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error
+{
+    /// The data ended before a complete structure was read
+    UnexpectedEnd,
+    /// The data does not start with the PNG signature
+    HeaderMismatch,
+    /// A chunk type that is not four ASCII letters
+    InvalidChunkType(String),
+    /// A chunk whose stored CRC does not match its type and data
+    InvalidCrc {chunk_type: ChunkType, stored: u32, computed: u32},
+    /// Chunk data that was expected to be UTF-8 text
+    InvalidUtf8,
+    /// No chunk of the requested type
+    ChunkNotFound(String),
+    /// Any other violation of the PNG or APNG format
+    Malformed(String),
+}
+
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+
+/// Returns early with `Error::Malformed` and a formatted message
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err($crate::error::Error::Malformed(::alloc::format!($($arg)*)))
+    };
+}
+
+pub(crate) use bail;
+
+
+impl From<TryFromSliceError> for Error
+{
+    fn from(_: TryFromSliceError) -> Self
+    {
+        Self::UnexpectedEnd
+    }
+}
+
+
+impl fmt::Display for Error
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Self::UnexpectedEnd => write!(f, "Unexpected end of data"),
+            Self::HeaderMismatch => write!(f, "Header mismatch"),
+            Self::InvalidChunkType(chunk_type) => write!(f, "Invalid chunk type: {chunk_type:?}"),
+            Self::InvalidCrc {chunk_type, stored, computed} =>
+                write!(f, "Invalid CRC in {chunk_type}: stored {stored:#010x}, expected {computed:#010x}"),
+            Self::InvalidUtf8 => write!(f, "Chunk data is not valid UTF-8"),
+            Self::ChunkNotFound(chunk_type) => write!(f, "Chunk not found: {chunk_type}"),
+            Self::Malformed(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn malformed(length: usize) -> Result<()> {
+        bail!("Invalid length {length}")
+    }
+
+    #[test]
+    fn test_bail() {
+        assert_eq!(malformed(3), Err(Error::Malformed("Invalid length 3".to_string())));
+    }
+
+    #[test]
+    fn test_display() {
+        let error = Error::InvalidCrc {chunk_type: ChunkType::new(*b"IEND").unwrap(), stored: 1, computed: 0xae426082};
+        assert_eq!(error.to_string(), "Invalid CRC in IEND: stored 0x00000001, expected 0xae426082");
+        assert_eq!(Error::InvalidChunkType("Ru1t".to_string()).to_string(), "Invalid chunk type: \"Ru1t\"");
+    }
+}
@@ -0,0 +1,148 @@
+use crate::chunk::Chunk;
+
+use crate::error::{Error, Result, bail};
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+use core::fmt;
+
+
+/// The image header stored in the `IHDR` chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageHeader
+{
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    pub interlace_method: u8,
+}
+
+
+impl ImageHeader
+{
+    pub const CHUNK_TYPE: &'static str = "IHDR";
+    pub const LENGTH: usize = 13;
+    /// Largest width or height allowed by the PNG specification, 2^31-1
+    pub const MAX_DIMENSION: u32 = i32::MAX as u32;
+
+    pub fn to_bytes(&self) -> Vec<u8>
+    {
+        let mut bytes = Vec::with_capacity(Self::LENGTH);
+        bytes.extend_from_slice(&self.width.to_be_bytes());
+        bytes.extend_from_slice(&self.height.to_be_bytes());
+        bytes.extend_from_slice(&[
+            self.bit_depth,
+            self.color_type,
+            self.compression_method,
+            self.filter_method,
+            self.interlace_method,
+        ]);
+
+        bytes
+    }
+}
+
+
+impl TryFrom<&Chunk> for ImageHeader
+{
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self>
+    {
+        let chunk_type = chunk.chunk_type().to_string();
+        if chunk_type != Self::CHUNK_TYPE
+        {
+            bail!("Expected {} chunk, found {chunk_type}", Self::CHUNK_TYPE)
+        }
+
+        let data = chunk.data();
+        if data.len() != Self::LENGTH
+        {
+            bail!("Invalid {} length {}", Self::CHUNK_TYPE, data.len())
+        }
+
+        let header = Self {
+            width: u32::from_be_bytes(data[0..4].try_into()?),
+            height: u32::from_be_bytes(data[4..8].try_into()?),
+            bit_depth: data[8],
+            color_type: data[9],
+            compression_method: data[10],
+            filter_method: data[11],
+            interlace_method: data[12],
+        };
+
+        if header.width == 0 || header.height == 0 || header.width > Self::MAX_DIMENSION || header.height > Self::MAX_DIMENSION
+        {
+            bail!("Invalid image dimensions {}x{}", header.width, header.height)
+        }
+
+        Ok(header)
+    }
+}
+
+
+impl fmt::Display for ImageHeader
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        writeln!(f, "Header {{",)?;
+        writeln!(f, "  Size: {}x{}", self.width, self.height)?;
+        writeln!(f, "  Bit depth: {}", self.bit_depth)?;
+        writeln!(f, "  Color type: {}", self.color_type)?;
+        writeln!(f, "  Interlace: {}", self.interlace_method)?;
+        writeln!(f, "}}",)?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_header_from_chunk() {
+        let data = vec![0, 0, 0, 50, 0, 0, 0, 40, 8, 6, 0, 0, 0];
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), data);
+        let header = ImageHeader::try_from(&chunk).unwrap();
+
+        assert_eq!(header.width, 50);
+        assert_eq!(header.height, 40);
+        assert_eq!(header.bit_depth, 8);
+        assert_eq!(header.color_type, 6);
+    }
+
+    #[test]
+    fn test_header_to_bytes() {
+        let data = vec![0, 0, 0, 50, 0, 0, 0, 40, 8, 6, 0, 0, 1];
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), data.clone());
+        assert_eq!(ImageHeader::try_from(&chunk).unwrap().to_bytes(), data);
+    }
+
+    #[test]
+    fn test_header_invalid_length() {
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 12]);
+        assert!(ImageHeader::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_header_invalid_dimensions() {
+        for size in [[0, 0, 0, 0], [0x80, 0, 0, 0]] {
+            let data = [&size[..], &[0, 0, 0, 1, 8, 6, 0, 0, 0]].concat();
+            let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), data);
+            assert!(ImageHeader::try_from(&chunk).is_err());
+        }
+    }
+
+    #[test]
+    fn test_header_wrong_type() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0; 13]);
+        assert!(ImageHeader::try_from(&chunk).is_err());
+    }
+}
@@ -1,6 +1,57 @@
-pub mod args;
-mod chunk;
-mod chunk_type;
-pub mod commands;
+//! Reading, modifying and writing PNG files chunk by chunk
+//!
+//! ```
+//! use pngme::prelude::*;
+//!
+//! # let idat = Chunk::new("IDAT".parse()?, vec![0x78, 0x01, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01]);
+//! # let header = ImageHeader {width: 1, height: 1, bit_depth: 8, color_type: 0,
+//! #     compression_method: 0, filter_method: 0, interlace_method: 0};
+//! # let bytes = Png::builder().header(header).chunk(idat).build()?.as_bytes();
+//! let mut png = Png::try_from(&bytes[..])?;
+//! png.append_chunk(Chunk::new("ruSt".parse()?, b"hidden message".to_vec()));
+//!
+//! let types: Vec<String> = png.iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+//! assert_eq!(types, ["IHDR", "IDAT", "ruSt", "IEND"]);
+//! assert_eq!(png.chunk_by_type("ruSt").unwrap().data_as_string()?, "hidden message");
+//! # Ok::<(), pngme::error::Error>(())
+//! ```
+//!
+//! Without the default `std` feature the chunk and PNG types only need `alloc`.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "analysis")]
+pub mod analysis;
+pub mod apng;
+#[cfg(feature = "async")]
+pub mod async_reader;
+#[cfg(feature = "camouflage")]
+pub mod camouflage;
+pub mod checksum;
+pub mod chunk;
+pub mod chunk_ref;
+pub mod chunk_type;
+#[cfg(feature = "crypto")]
+pub mod encdec;
+pub mod error;
+pub mod ihdr;
+#[cfg(feature = "integrity")]
+pub mod manifest;
+#[cfg(feature = "compression")]
+pub mod pixels;
 pub mod png;
-mod encdec;
+pub mod png_ref;
+pub mod prelude;
+#[cfg(feature = "std")]
+pub mod query;
+#[cfg(feature = "recipients")]
+pub mod recipients;
+pub mod scan;
+#[cfg(feature = "signing")]
+pub mod signature;
+#[cfg(feature = "compression")]
+pub mod watermark;
+#[cfg(feature = "serde")]
+mod serde_impls;
@@ -0,0 +1,383 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+use crate::error::{Result, bail};
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use alloc::vec;
+use sha2::{Digest as _, Sha256};
+
+use core::fmt;
+
+
+/// SHA-256 of the bytes of a chunk as stored in the file: length, type, data and CRC
+pub type Digest = [u8; 32];
+
+
+/// Type of the private chunk holding the manifest written by `seal`, placed before `IEND`
+pub const SEAL_CHUNK_TYPE: &str = "seAL";
+
+/// Version of the seal chunk layout
+const SEAL_VERSION: u8 = 1;
+
+
+/// The digest of one chunk and where it was
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry
+{
+    pub index: u32,
+    pub chunk_type: ChunkType,
+    pub digest: Digest,
+}
+
+
+/// Digests of the chunks of a PNG in order, to find out later which chunks changed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest
+{
+    entries: Vec<Entry>,
+}
+
+
+/// A difference between a manifest and the chunks of a PNG
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change
+{
+    /// A chunk that is not in the manifest, at its index in the PNG
+    Added {index: usize, chunk_type: ChunkType},
+    /// A chunk of the manifest that is gone, at its index in the manifest
+    Removed {index: usize, chunk_type: ChunkType},
+    /// A chunk whose bytes changed, at its index in the PNG
+    Altered {index: usize, chunk_type: ChunkType},
+    /// An unchanged chunk out of its original order, at its index in the manifest and in the PNG
+    Moved {from: usize, to: usize, chunk_type: ChunkType},
+}
+
+
+pub fn digest(chunk: &Chunk) -> Digest
+{
+    Sha256::digest(chunk.as_bytes()).into()
+}
+
+
+impl Entry
+{
+    /// Bytes of an entry: index, type and digest
+    pub const LENGTH: usize = 4 + 4 + 32;
+}
+
+
+impl Manifest
+{
+    /// The digests of all chunks of `png`, except those of type `excluded` such as the chunk
+    /// the manifest itself is stored in
+    pub fn of(png: &Png, excluded: &str) -> Self
+    {
+        let entries = png.chunks()
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.chunk_type().bytes() != excluded.as_bytes())
+            .map(|(index, chunk)| Entry {index: index as u32, chunk_type: chunk.chunk_type().clone(), digest: digest(chunk)})
+            .collect();
+
+        Self {entries}
+    }
+
+    pub fn entries(&self) -> &[Entry]
+    {
+        &self.entries
+    }
+
+    /// The number of entries as a big endian u32, followed by the entries
+    pub fn as_bytes(&self) -> Vec<u8>
+    {
+        let mut bytes = Vec::with_capacity(4 + self.entries.len() * Entry::LENGTH);
+        bytes.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for entry in &self.entries
+        {
+            bytes.extend_from_slice(&entry.index.to_be_bytes());
+            bytes.extend_from_slice(&entry.chunk_type.bytes());
+            bytes.extend_from_slice(&entry.digest);
+        }
+        bytes
+    }
+
+    /// Parses a manifest written by `as_bytes`, which must span all of `bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self>
+    {
+        let count = u32::from_be_bytes(bytes.get(..4).unwrap_or_default().try_into()?) as usize;
+        let body = &bytes[4..];
+        if Some(body.len()) != count.checked_mul(Entry::LENGTH)
+        {
+            bail!("Manifest of {count} entries has {} bytes of entries", body.len());
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        for entry in body.chunks_exact(Entry::LENGTH)
+        {
+            entries.push(Entry {
+                index: u32::from_be_bytes(entry[..4].try_into()?),
+                chunk_type: ChunkType::new(entry[4..8].try_into()?)?,
+                digest: entry[8..].try_into()?,
+            });
+        }
+
+        Ok(Self {entries})
+    }
+
+    /// How the chunks of `current` differ from this manifest. Chunks are matched by digest first,
+    /// and the fewest matched chunks that explain a change of order are reported as moved.
+    /// Chunks left over on both sides are then paired in order by type as altered
+    pub fn compare(&self, current: &Manifest) -> Vec<Change>
+    {
+        let mut by_digest: BTreeMap<&Digest, VecDeque<usize>> = BTreeMap::new();
+        for (position, entry) in current.entries.iter().enumerate()
+        {
+            by_digest.entry(&entry.digest).or_default().push_back(position);
+        }
+
+        let mut matched = vec![false; current.entries.len()];
+        let mut pairs = vec![];
+        let mut unmatched = vec![];
+        for entry in &self.entries
+        {
+            match by_digest.get_mut(&entry.digest).and_then(VecDeque::pop_front)
+            {
+                Some(position) => {
+                    matched[position] = true;
+                    pairs.push((entry, position));
+                }
+                None => unmatched.push(entry),
+            }
+        }
+
+        let positions: Vec<usize> = pairs.iter().map(|(_, position)| *position).collect();
+        let in_order = longest_increasing(&positions);
+
+        let mut changes = vec![];
+        for ((entry, position), in_order) in pairs.into_iter().zip(in_order)
+        {
+            if !in_order
+            {
+                let (from, to) = (entry.index as usize, current.entries[position].index as usize);
+                changes.push(Change::Moved {from, to, chunk_type: entry.chunk_type.clone()});
+            }
+        }
+
+        let mut by_type: BTreeMap<[u8; 4], VecDeque<usize>> = BTreeMap::new();
+        for (position, entry) in current.entries.iter().enumerate().filter(|(position, _)| !matched[*position])
+        {
+            by_type.entry(entry.chunk_type.bytes()).or_default().push_back(position);
+        }
+
+        for entry in unmatched
+        {
+            let chunk_type = entry.chunk_type.clone();
+            match by_type.get_mut(&chunk_type.bytes()).and_then(VecDeque::pop_front)
+            {
+                Some(position) => {
+                    matched[position] = true;
+                    changes.push(Change::Altered {index: current.entries[position].index as usize, chunk_type});
+                }
+                None => changes.push(Change::Removed {index: entry.index as usize, chunk_type}),
+            }
+        }
+
+        for (_, entry) in current.entries.iter().enumerate().filter(|(position, _)| !matched[*position])
+        {
+            changes.push(Change::Added {index: entry.index as usize, chunk_type: entry.chunk_type.clone()});
+        }
+
+        changes
+    }
+}
+
+
+/// Marks the elements of the longest strictly increasing subsequence of `values`
+fn longest_increasing(values: &[usize]) -> Vec<bool>
+{
+    // `tails[k]` is the index of the smallest last value of an increasing run of length `k + 1`
+    let mut tails: Vec<usize> = vec![];
+    let mut previous = vec![None; values.len()];
+
+    for (index, &value) in values.iter().enumerate()
+    {
+        let length = tails.partition_point(|&tail| values[tail] < value);
+        previous[index] = length.checked_sub(1).map(|k| tails[k]);
+        if length == tails.len()
+        {
+            tails.push(index);
+        }
+        else
+        {
+            tails[length] = index;
+        }
+    }
+
+    let mut in_run = vec![false; values.len()];
+    let mut next = tails.last().copied();
+    while let Some(index) = next
+    {
+        in_run[index] = true;
+        next = previous[index];
+    }
+
+    in_run
+}
+
+
+/// Stores the manifest of `png` in a `seAL` chunk before `IEND`, replacing any previous seal
+pub fn seal(png: &mut Png) -> Result<()>
+{
+    while png.remove_chunk(SEAL_CHUNK_TYPE).is_ok() {}
+
+    let mut data = vec![SEAL_VERSION];
+    data.extend_from_slice(&Manifest::of(png, SEAL_CHUNK_TYPE).as_bytes());
+    png.append_chunk(Chunk::new(SEAL_CHUNK_TYPE.parse()?, data));
+
+    Ok(())
+}
+
+
+/// Compares the chunks of `png` with the manifest of its seal. Fails if there is no single valid seal
+pub fn check(png: &Png) -> Result<Vec<Change>>
+{
+    let mut chunks = png.chunks_by_type(SEAL_CHUNK_TYPE);
+    let chunk = match (chunks.next(), chunks.next())
+    {
+        (Some(chunk), None) => chunk,
+        (None, _) => bail!("No {SEAL_CHUNK_TYPE} seal chunk"),
+        (Some(_), Some(_)) => bail!("More than one {SEAL_CHUNK_TYPE} seal chunk"),
+    };
+
+    let sealed = match chunk.data().split_first()
+    {
+        Some((&SEAL_VERSION, manifest)) => Manifest::from_bytes(manifest)?,
+        Some((version, _)) => bail!("Unsupported seal chunk version {version}"),
+        None => bail!("Empty seal chunk"),
+    };
+
+    Ok(sealed.compare(&Manifest::of(png, SEAL_CHUNK_TYPE)))
+}
+
+
+impl fmt::Display for Change
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Self::Added {index, chunk_type} => write!(f, "added {chunk_type} chunk at index {index}"),
+            Self::Removed {index, chunk_type} => write!(f, "removed {chunk_type} chunk that was at index {index}"),
+            Self::Altered {index, chunk_type} => write!(f, "altered {chunk_type} chunk at index {index}"),
+            Self::Moved {from, to, chunk_type} => write!(f, "moved {chunk_type} chunk from index {from} to {to}"),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+    use core::str::FromStr;
+
+    fn testing_png() -> Png {
+        Png::try_from(&PNG_FILE[..]).unwrap()
+    }
+
+    fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let manifest = Manifest::of(&testing_png(), SEAL_CHUNK_TYPE);
+        let bytes = manifest.as_bytes();
+        assert_eq!(bytes.len(), 4 + manifest.entries().len() * Entry::LENGTH);
+        assert_eq!(Manifest::from_bytes(&bytes).unwrap(), manifest);
+
+        assert!(Manifest::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Manifest::from_bytes(&bytes[..2]).is_err());
+        assert!(Manifest::from_bytes(&[0xff; 4]).is_err());
+    }
+
+    #[test]
+    fn test_excluded() {
+        let mut png = testing_png();
+        let manifest = Manifest::of(&png, SEAL_CHUNK_TYPE);
+
+        png.append_chunk(chunk("seAL", b"seal"));
+        assert_eq!(Manifest::of(&png, SEAL_CHUNK_TYPE).entries().len(), manifest.entries().len());
+        assert!(manifest.compare(&Manifest::of(&png, SEAL_CHUNK_TYPE)).is_empty());
+
+        png.append_chunk(chunk("siGN", b"signature"));
+        assert!(matches!(manifest.compare(&Manifest::of(&png, SEAL_CHUNK_TYPE))[..], [Change::Added {..}]));
+    }
+
+    #[test]
+    fn test_longest_increasing() {
+        assert!(longest_increasing(&[]).is_empty());
+        assert_eq!(longest_increasing(&[0, 1, 2]), vec![true; 3]);
+        assert_eq!(longest_increasing(&[0, 3, 1, 2, 4]), vec![true, false, true, true, true]);
+        assert_eq!(longest_increasing(&[4, 0, 1, 2, 3]), vec![false, true, true, true, true]);
+    }
+
+    #[test]
+    fn test_compare_moved() {
+        let mut png = testing_png();
+        png.append_chunk(chunk("tEXt", b"Title\0first"));
+        png.append_chunk(chunk("tEXt", b"Author\0second"));
+        let sealed = Manifest::of(&png, SEAL_CHUNK_TYPE);
+
+        let count = png.chunks().len();
+        png.chunks_mut()[count - 3..count - 1].swap(0, 1);
+
+        let changes = sealed.compare(&Manifest::of(&png, SEAL_CHUNK_TYPE));
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(&changes[0], Change::Moved {from, to, ..} if from.abs_diff(*to) == 1));
+    }
+
+    #[test]
+    fn test_seal_check() {
+        let mut png = testing_png();
+        assert!(check(&png).is_err());
+
+        seal(&mut png).unwrap();
+        seal(&mut png).unwrap();
+        let mut png = Png::try_from(&png.as_bytes()[..]).unwrap();
+        assert_eq!(png.chunks_by_type(SEAL_CHUNK_TYPE).count(), 1);
+        assert!(check(&png).unwrap().is_empty());
+
+        let idat = png.chunks().iter().position(|c| c.chunk_type().to_string() == "IDAT").unwrap();
+        png.chunks_mut()[idat] = chunk("IDAT", b"forged");
+        assert_eq!(check(&png).unwrap(), vec![Change::Altered {index: idat, chunk_type: ChunkType::from_str("IDAT").unwrap()}]);
+
+        png.append_chunk(chunk("seAL", &[2]));
+        assert!(check(&png).is_err());
+    }
+
+    #[test]
+    fn test_compare() {
+        let mut png = testing_png();
+        png.append_chunk(chunk("tEXt", b"Comment\0original"));
+        png.append_chunk(chunk("zTXt", b"gone"));
+        let signed = Manifest::of(&png, SEAL_CHUNK_TYPE);
+        let count = png.chunks().len();
+
+        let text = png.chunks().iter().position(|c| c.chunk_type().to_string() == "tEXt").unwrap();
+        png.chunks_mut()[text] = chunk("tEXt", b"Comment\0forged");
+        png.remove_chunk("zTXt").unwrap();
+        png.append_chunk(chunk("ruSt", b"new"));
+
+        let changes = signed.compare(&Manifest::of(&png, SEAL_CHUNK_TYPE));
+        assert_eq!(changes, vec![
+            Change::Altered {index: text, chunk_type: ChunkType::from_str("tEXt").unwrap()},
+            Change::Removed {index: count - 2, chunk_type: ChunkType::from_str("zTXt").unwrap()},
+            Change::Added {index: count - 2, chunk_type: ChunkType::from_str("ruSt").unwrap()},
+        ]);
+        assert_eq!(changes[0].to_string(), format!("altered tEXt chunk at index {text}"));
+    }
+}
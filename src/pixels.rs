@@ -0,0 +1,444 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::ImageHeader;
+use crate::png::Png;
+
+use anyhow::{Context, Result, bail};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use std::io::prelude::*;
+use std::str::FromStr;
+
+
+/// A decoded image with 8-bit RGBA pixels in row-major order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image
+{
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+
+/// Adam7 passes as (x start, y start, x step, y step)
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+
+impl Image
+{
+    /// Creates a fully transparent image. Panics if its size overflows `usize`
+    pub fn new(width: u32, height: u32) -> Self
+    {
+        Self::try_new(width, height).expect("Image too large")
+    }
+
+    /// Creates a fully transparent image, failing if its size overflows `usize`
+    pub fn try_new(width: u32, height: u32) -> Result<Self>
+    {
+        let length = (width as usize).checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .with_context(|| format!("Image of {width}x{height} pixels too large"))?;
+
+        Ok(Self {width, height, pixels: vec![0; length]})
+    }
+
+    fn offset(&self, x: u32, y: u32) -> usize
+    {
+        (y as usize * self.width as usize + x as usize) * 4
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4]
+    {
+        let i = self.offset(x, y);
+        self.pixels[i..i + 4].try_into().expect("Pixel out of bounds")
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, rgba: [u8; 4])
+    {
+        let i = self.offset(x, y);
+        self.pixels[i..i + 4].copy_from_slice(&rgba);
+    }
+
+    /// Encodes the image as an RGBA PNG with a single `IDAT` chunk
+    pub fn to_png(&self) -> Result<Png>
+    {
+        let header = ImageHeader {
+            width: self.width,
+            height: self.height,
+            bit_depth: 8,
+            color_type: 6,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: 0,
+        };
+
+        let png = Png::builder()
+            .header(header)
+            .chunk(Chunk::new(ChunkType::from_str("IDAT")?, self.compress()?))
+            .build()?;
+
+        Ok(png)
+    }
+
+    /// Filters (type `None`) and zlib compresses the pixels as RGBA scanlines
+    pub fn compress(&self) -> Result<Vec<u8>>
+    {
+        let row_bytes = self.width as usize * 4;
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+
+        for row in self.pixels.chunks(row_bytes)
+        {
+            encoder.write_all(&[0])?;
+            encoder.write_all(row)?;
+        }
+
+        Ok(encoder.finish()?)
+    }
+}
+
+
+/// Decodes the `IDAT` data of a PNG into RGBA pixels
+pub fn decode(png: &Png) -> Result<Image>
+{
+    let header = png.image_header()?;
+    let data: Vec<&[u8]> = png.chunks_by_type("IDAT").map(|c| c.data()).collect();
+    if data.is_empty()
+    {
+        bail!("Missing IDAT chunk")
+    }
+
+    decode_data(png, &header, &data)
+}
+
+
+/// Decodes zlib stream pieces belonging to `png`, with dimensions and interlacing taken from `header`
+pub fn decode_data(png: &Png, header: &ImageHeader, data: &[&[u8]]) -> Result<Image>
+{
+    let palette = png.chunk_by_type("PLTE").map(|c| c.data());
+    let transparency = png.chunk_by_type("tRNS").map(|c| c.data());
+    let converter = Converter::new(header, palette, transparency)?;
+
+    let mut compressed: Vec<u8> = vec![];
+    for piece in data
+    {
+        compressed.extend_from_slice(piece);
+    }
+
+    let (width, height) = (header.width as usize, header.height as usize);
+    let expected = match header.interlace_method
+    {
+        0 => converter.pass_length(width, height),
+        _ => ADAM7.iter().try_fold(0usize, |total, &(x0, y0, dx, dy)| {
+            converter.pass_length((width + dx - 1 - x0) / dx, (height + dy - 1 - y0) / dy)
+                .and_then(|length| total.checked_add(length))
+        }),
+    };
+    let expected = expected.with_context(|| format!("Image of {width}x{height} pixels too large"))?;
+
+    // Inflate no more than the header calls for, and allocate only once the data is there
+    let mut raw = vec![];
+    ZlibDecoder::new(&compressed[..]).take(expected as u64).read_to_end(&mut raw)?;
+    if raw.len() < expected
+    {
+        bail!("Image data too short: {} < {expected}", raw.len())
+    }
+
+    let mut image = Image::try_new(header.width, header.height)?;
+
+    match header.interlace_method
+    {
+        0 => {
+            converter.decode_pass(&raw, width, height, |x, y, rgba| image.set_pixel(x as u32, y as u32, rgba))?;
+        }
+        1 => {
+            let mut rest = &raw[..];
+            for (x0, y0, dx, dy) in ADAM7
+            {
+                let pass_width = (width + dx - 1 - x0) / dx;
+                let pass_height = (height + dy - 1 - y0) / dy;
+                if pass_width == 0 || pass_height == 0
+                {
+                    continue;
+                }
+
+                let used = converter.decode_pass(rest, pass_width, pass_height, |x, y, rgba| {
+                    image.set_pixel((x0 + x * dx) as u32, (y0 + y * dy) as u32, rgba)
+                })?;
+                rest = &rest[used..];
+            }
+        }
+        method => bail!("Unsupported interlace method {method}"),
+    }
+
+    Ok(image)
+}
+
+
+/// Converts scanlines of any PNG color type and bit depth to RGBA
+struct Converter<'a>
+{
+    color_type: u8,
+    bit_depth: u8,
+    channels: usize,
+    palette: Option<&'a [u8]>,
+    transparency: Option<&'a [u8]>,
+}
+
+
+impl<'a> Converter<'a>
+{
+    fn new(header: &ImageHeader, palette: Option<&'a [u8]>, transparency: Option<&'a [u8]>) -> Result<Self>
+    {
+        let (channels, depths): (usize, &[u8]) = match header.color_type
+        {
+            0 => (1, &[1, 2, 4, 8, 16]),
+            2 => (3, &[8, 16]),
+            3 => (1, &[1, 2, 4, 8]),
+            4 => (2, &[8, 16]),
+            6 => (4, &[8, 16]),
+            color_type => bail!("Invalid color type {color_type}"),
+        };
+
+        if !depths.contains(&header.bit_depth)
+        {
+            bail!("Invalid bit depth {} for color type {}", header.bit_depth, header.color_type)
+        }
+
+        if header.color_type == 3 && palette.is_none()
+        {
+            bail!("Missing PLTE chunk")
+        }
+
+        Ok(Self {color_type: header.color_type, bit_depth: header.bit_depth, channels, palette, transparency})
+    }
+
+    fn bits_per_pixel(&self) -> usize
+    {
+        self.channels * self.bit_depth as usize
+    }
+
+    /// Number of raw bytes in a (sub)image, with the filter type byte of each row
+    fn pass_length(&self, width: usize, height: usize) -> Option<usize>
+    {
+        if width == 0 || height == 0
+        {
+            return Some(0);
+        }
+
+        let row_bytes = width.checked_mul(self.bits_per_pixel())?.checked_add(7)? / 8;
+        (row_bytes + 1).checked_mul(height)
+    }
+
+    /// Unfilters and converts one (sub)image, returning the number of raw bytes consumed
+    fn decode_pass<F>(&self, raw: &[u8], width: usize, height: usize, mut put: F) -> Result<usize>
+        where F: FnMut(usize, usize, [u8; 4])
+    {
+        let needed = self.pass_length(width, height).context("Image too large")?;
+        let row_bytes = (width * self.bits_per_pixel() + 7) / 8;
+        let bpp = (self.bits_per_pixel() / 8).max(1);
+        if raw.len() < needed
+        {
+            bail!("Image data too short: {} < {needed}", raw.len())
+        }
+
+        let mut previous = vec![0u8; row_bytes];
+        let mut current = vec![0u8; row_bytes];
+
+        for y in 0..height
+        {
+            let line = &raw[y * (row_bytes + 1)..(y + 1) * (row_bytes + 1)];
+            current.copy_from_slice(&line[1..]);
+            unfilter(line[0], bpp, &previous, &mut current)?;
+
+            for x in 0..width
+            {
+                put(x, y, self.pixel(&current, x));
+            }
+
+            std::mem::swap(&mut previous, &mut current);
+        }
+
+        Ok(needed)
+    }
+
+    fn sample(&self, row: &[u8], index: usize) -> u16
+    {
+        match self.bit_depth
+        {
+            16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+            8 => row[index] as u16,
+            depth => {
+                let bit = index * depth as usize;
+                let shift = 8 - depth as usize - bit % 8;
+                (row[bit / 8] >> shift) as u16 & ((1 << depth) - 1)
+            }
+        }
+    }
+
+    fn scale(&self, value: u16) -> u8
+    {
+        match self.bit_depth
+        {
+            16 => (value >> 8) as u8,
+            8 => value as u8,
+            depth => (value * 255 / ((1 << depth) - 1)) as u8,
+        }
+    }
+
+    fn transparent_key(&self, index: usize) -> Option<u16>
+    {
+        self.transparency
+            .filter(|t| t.len() >= index * 2 + 2)
+            .map(|t| u16::from_be_bytes([t[index * 2], t[index * 2 + 1]]))
+    }
+
+    fn pixel(&self, row: &[u8], x: usize) -> [u8; 4]
+    {
+        let base = x * self.channels;
+        match self.color_type
+        {
+            0 => {
+                let v = self.sample(row, base);
+                let g = self.scale(v);
+                let a = if self.transparent_key(0) == Some(v) { 0 } else { 255 };
+                [g, g, g, a]
+            }
+            2 => {
+                let rgb = [self.sample(row, base), self.sample(row, base + 1), self.sample(row, base + 2)];
+                let opaque = (0..3).any(|i| self.transparent_key(i) != Some(rgb[i]));
+                [self.scale(rgb[0]), self.scale(rgb[1]), self.scale(rgb[2]), if opaque { 255 } else { 0 }]
+            }
+            3 => {
+                let index = self.sample(row, base) as usize;
+                let palette = self.palette.unwrap_or_default();
+                let rgb = palette.get(index * 3..index * 3 + 3).unwrap_or(&[0, 0, 0]);
+                let a = self.transparency.and_then(|t| t.get(index)).copied().unwrap_or(255);
+                [rgb[0], rgb[1], rgb[2], a]
+            }
+            4 => {
+                let g = self.scale(self.sample(row, base));
+                [g, g, g, self.scale(self.sample(row, base + 1))]
+            }
+            _ => [
+                self.scale(self.sample(row, base)),
+                self.scale(self.sample(row, base + 1)),
+                self.scale(self.sample(row, base + 2)),
+                self.scale(self.sample(row, base + 3)),
+            ],
+        }
+    }
+}
+
+
+fn paeth(a: u8, b: u8, c: u8) -> u8
+{
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+
+    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+}
+
+
+fn unfilter(filter: u8, bpp: usize, previous: &[u8], current: &mut [u8]) -> Result<()>
+{
+    for i in 0..current.len()
+    {
+        let left = if i >= bpp { current[i - bpp] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+
+        current[i] = current[i].wrapping_add(match filter
+        {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => bail!("Invalid filter type {filter}"),
+        });
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_image() -> Image {
+        let mut image = Image::new(3, 2);
+        image.set_pixel(0, 0, [255, 0, 0, 255]);
+        image.set_pixel(1, 0, [0, 255, 0, 128]);
+        image.set_pixel(2, 1, [0, 0, 255, 255]);
+        image
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let image = testing_image();
+        let png = image.to_png().unwrap();
+        let png = Png::try_from(&png.as_bytes()[..]).unwrap();
+        assert_eq!(decode(&png).unwrap(), image);
+    }
+
+    #[test]
+    fn test_unfilter() {
+        let previous = [10, 20, 30, 40];
+        let mut current = [1, 1, 1, 1];
+        unfilter(2, 1, &previous, &mut current).unwrap();
+        assert_eq!(current, [11, 21, 31, 41]);
+
+        let mut current = [1, 1, 1, 1];
+        unfilter(1, 1, &[0; 4], &mut current).unwrap();
+        assert_eq!(current, [1, 2, 3, 4]);
+
+        assert!(unfilter(5, 1, &previous, &mut current).is_err());
+    }
+
+    #[test]
+    fn test_decode_grayscale_low_bit_depth() {
+        let header = ImageHeader {
+            width: 4,
+            height: 1,
+            bit_depth: 2,
+            color_type: 0,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method: 0,
+        };
+        let converter = Converter::new(&header, None, None).unwrap();
+
+        let mut pixels = vec![];
+        converter.decode_pass(&[0, 0b00_01_10_11], 4, 1, |_, _, rgba| pixels.push(rgba[0])).unwrap();
+        assert_eq!(pixels, vec![0, 85, 170, 255]);
+    }
+
+    #[test]
+    fn test_decode_image_file() {
+        let png = Png::try_from(&crate::png::tests::PNG_FILE[..]).unwrap();
+        let image = decode(&png).unwrap();
+        assert_eq!((image.width, image.height), (50, 50));
+    }
+
+    #[test]
+    fn test_decode_oversized() {
+        let png = testing_image().to_png().unwrap();
+        let idat = png.chunk_by_type("IDAT").unwrap().data().to_vec();
+        for interlace_method in [0, 1] {
+            let header = ImageHeader {width: ImageHeader::MAX_DIMENSION, height: 100_000, interlace_method, ..png.image_header().unwrap()};
+            assert!(decode_data(&png, &header, &[&idat]).is_err());
+        }
+        assert!(Image::try_new(u32::MAX, u32::MAX).is_err());
+    }
+}
@@ -1,16 +1,61 @@
+use crate::apng::{self, AnimationControl, Frame};
 use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::ihdr::ImageHeader;
+use crate::png_ref::PngRef;
 
-use anyhow::{Result, Error, bail};
+use crate::error::{Error, Result, bail};
 
-use std::fmt;
-use std::io;
-use std::io::prelude::*;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::vec;
 
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
 
+
+/// An owned PNG file: its chunks and any bytes after `IEND`
 #[derive(Debug)]
 pub struct Png
 {
     chunks: Vec<Chunk>,
+    trailer: Vec<u8>,
+}
+
+
+/// Assembles a valid `Png` from an image header and chunks
+///
+/// ```
+/// use pngme::prelude::*;
+///
+/// let header = ImageHeader {
+///     width: 1,
+///     height: 1,
+///     bit_depth: 8,
+///     color_type: 0,
+///     compression_method: 0,
+///     filter_method: 0,
+///     interlace_method: 0,
+/// };
+/// let idat = vec![0x78, 0x01, 0x63, 0x60, 0x00, 0x00, 0x00, 0x02, 0x00, 0x01];
+///
+/// let png = Png::builder()
+///     .header(header)
+///     .chunk(Chunk::new("IDAT".parse()?, idat))
+///     .chunk(Chunk::new("tEXt".parse()?, b"Comment\0built".to_vec()))
+///     .build()?;
+///
+/// assert_eq!(png.chunks().len(), 4);
+/// assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+/// # Ok::<(), pngme::error::Error>(())
+/// ```
+#[derive(Debug, Default)]
+pub struct PngBuilder
+{
+    header: Option<ImageHeader>,
+    chunks: Vec<Chunk>,
+    trailer: Vec<u8>,
 }
 
 
@@ -20,17 +65,39 @@ impl Png
 
     fn new(chunks: Vec<Chunk>) -> Self
     {
-        Self {chunks}
+        Self {chunks, trailer: vec![]}
     }
 
-    fn from_chunks(chunks: Vec<Chunk>) -> Self
+    /// Starts assembling a PNG, see `PngBuilder`
+    pub fn builder() -> PngBuilder
+    {
+        PngBuilder::default()
+    }
+
+    /// Wraps the chunks as they are, without checking their order
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Self
     {
         Self::new(chunks)
     }
 
+    /// Appends a chunk, keeping `IEND` as the last chunk if present
     pub fn append_chunk(&mut self, chunk: Chunk)
     {
-        self.chunks.push(chunk)
+        match self.chunks.last()
+        {
+            Some(last) if last.chunk_type().to_string() == "IEND" => {
+                let index = self.chunks.len() - 1;
+                self.chunks.insert(index, chunk)
+            }
+            _ => self.chunks.push(chunk),
+        }
+    }
+
+    /// Inserts a chunk before the chunk at `index`, e.g. an ancillary chunk that must come before `IDAT`.
+    /// Panics if `index` is past the last chunk
+    pub fn insert_chunk(&mut self, index: usize, chunk: Chunk)
+    {
+        self.chunks.insert(index, chunk)
     }
 
     fn get_chunk_index(&self, chunk_type: &str) -> Option<usize>
@@ -45,7 +112,7 @@ impl Png
         match self.get_chunk_index(chunk_type)
         {
             Some(index) => Ok(self.chunks.remove(index)),
-            None => bail!("Chunk not found: {chunk_type}")
+            None => Err(Error::ChunkNotFound(String::from(chunk_type))),
         }
     }
 
@@ -54,11 +121,34 @@ impl Png
         &Self::STANDARD_HEADER
     }
 
-    fn chunks(&self) -> &[Chunk]
+    /// Checks that `bytes` start with the PNG signature
+    pub(crate) fn check_header(bytes: &[u8]) -> Result<()>
+    {
+        match bytes.get(..Self::STANDARD_HEADER.len())
+        {
+            Some(header) if header == Self::STANDARD_HEADER => Ok(()),
+            _ => Err(Error::HeaderMismatch),
+        }
+    }
+
+    pub fn chunks(&self) -> &[Chunk]
     {
         &self.chunks
     }
 
+    /// The chunks for modification in place, e.g. replacing one with `Chunk::new`.
+    /// Use `append_chunk`, `insert_chunk` and `remove_chunk` to add or remove chunks
+    pub fn chunks_mut(&mut self) -> &mut [Chunk]
+    {
+        &mut self.chunks
+    }
+
+    /// Iterates over the chunks in file order
+    pub fn iter(&self) -> core::slice::Iter<'_, Chunk>
+    {
+        self.chunks.iter()
+    }
+
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk>
     {
         match self.get_chunk_index(chunk_type)
@@ -68,82 +158,203 @@ impl Png
         }
     }
 
-    pub fn as_bytes(&self) -> Vec<u8>
+    pub fn chunks_by_type<'a>(&'a self, chunk_type: &'a str) -> impl Iterator<Item = &'a Chunk> + 'a
     {
-        self.header()
+        self.chunks
             .iter()
-            .cloned()
-            .chain(self.chunks()
-                .iter()
-                .cloned()
-                .flat_map(|f| f.as_bytes()))
-            .collect()
+            .filter(move |e| e.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Parses the `IHDR` chunk, which must be the first chunk
+    pub fn image_header(&self) -> Result<ImageHeader>
+    {
+        match self.chunks.first()
+        {
+            Some(chunk) => ImageHeader::try_from(chunk),
+            None => bail!("Missing IHDR chunk"),
+        }
+    }
+
+    /// Like `try_from`, but keeps chunks whose stored CRC does not match their contents,
+    /// so that the file can be inspected, repaired or written back unchanged
+    pub fn try_from_unchecked(bytes: &[u8]) -> Result<Self>
+    {
+        Ok(PngRef::try_from_unchecked(bytes)?.to_png())
+    }
+
+    /// Fixes every wrong stored CRC and returns the index, stored and computed CRC of each
+    pub fn repair_crcs(&mut self) -> Vec<(usize, u32, u32)>
+    {
+        let mut repaired = vec![];
+        for (index, chunk) in self.chunks.iter_mut().enumerate()
+        {
+            let stored_crc = chunk.stored_crc();
+            if chunk.repair_crc()
+            {
+                repaired.push((index, stored_crc, chunk.crc()));
+            }
+        }
+
+        repaired
+    }
+
+    /// Bytes found after the `IEND` chunk, such as a concatenated ZIP archive
+    pub fn trailer(&self) -> &[u8]
+    {
+        &self.trailer
+    }
+
+    pub(crate) fn set_trailer(&mut self, trailer: Vec<u8>)
+    {
+        self.trailer = trailer;
+    }
+
+    /// Removes and returns the bytes after `IEND`
+    pub fn take_trailer(&mut self) -> Vec<u8>
+    {
+        core::mem::take(&mut self.trailer)
+    }
+
+    pub fn is_animated(&self) -> bool
+    {
+        self.get_chunk_index(apng::ACTL).is_some()
+    }
+
+    pub fn animation_control(&self) -> Result<Option<AnimationControl>>
+    {
+        apng::animation_control(self.chunks())
+    }
+
+    pub fn frames(&self) -> Result<Vec<Frame<'_>>>
+    {
+        apng::frames(self.chunks())
+    }
+
+    /// Checks that the APNG chunks, if any, form a valid animation
+    pub fn validate_animation(&self) -> Result<()>
+    {
+        apng::validate(self.chunks())
+    }
+
+    /// Number of bytes the serialized PNG takes
+    pub fn byte_len(&self) -> usize
+    {
+        Self::STANDARD_HEADER.len()
+            + self.chunks.iter().map(Chunk::byte_len).sum::<usize>()
+            + self.trailer.len()
+    }
+
+    /// Writes the signature, all chunks and the trailer
+    #[cfg(feature = "std")]
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()>
+    {
+        writer.write_all(self.header())?;
+        for chunk in &self.chunks
+        {
+            chunk.write_to(writer)?;
+        }
+        writer.write_all(&self.trailer)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8>
+    {
+        let mut bytes = Vec::with_capacity(self.byte_len());
+        bytes.extend_from_slice(self.header());
+        for chunk in &self.chunks
+        {
+            chunk.extend_bytes(&mut bytes);
+        }
+        bytes.extend_from_slice(&self.trailer);
+        bytes
     }
 }
 
 
-impl TryFrom<&[u8]> for Png
+impl PngBuilder
 {
-    type Error = Error;
+    /// Sets the image header, written as the `IHDR` chunk
+    pub fn header(mut self, header: ImageHeader) -> Self
+    {
+        self.header = Some(header);
+        self
+    }
 
-    fn try_from(bytes: &[u8]) -> Result<Self>
+    pub fn chunk(mut self, chunk: Chunk) -> Self
     {
-        let mut chunks: Vec<Chunk> = vec![];
+        self.chunks.push(chunk);
+        self
+    }
 
-        let mut reader = io::BufReader::new(bytes);
-        let mut header_buffer: [u8; 8] = [0; 8];
+    pub fn chunks<I: IntoIterator<Item = Chunk>>(mut self, chunks: I) -> Self
+    {
+        self.chunks.extend(chunks);
+        self
+    }
 
-        reader.read_exact(&mut header_buffer)?;
+    /// Bytes to write after `IEND`
+    pub fn trailer(mut self, trailer: Vec<u8>) -> Self
+    {
+        self.trailer = trailer;
+        self
+    }
 
-        if header_buffer != Self::STANDARD_HEADER
+    /// Checks the chunk order and adds `IHDR` from the header and a missing `IEND`.
+    /// Fails without exactly one `IHDR`, without `IDAT`, or with an invalid animation
+    pub fn build(self) -> Result<Png>
+    {
+        let mut chunks = Vec::with_capacity(self.chunks.len() + 2);
+        if let Some(header) = self.header
         {
-            bail!("Header mismatch")
+            chunks.push(Chunk::new(ChunkType::new(*b"IHDR")?, header.to_bytes()));
         }
+        chunks.extend(self.chunks);
 
-        loop
+        let count = |name: &str| chunks.iter().filter(|c| c.chunk_type().to_string() == name).count();
+        if count(ImageHeader::CHUNK_TYPE) != 1
         {
-            /* Read chunk:
-               Length 4 byte
-               ChunkType 4 byte
-               ChunkData Length byte
-               CRC 4 byte */
-
-            let mut data_length_buffer: [u8; 4] = [0; 4];
-            match reader.read_exact(&mut data_length_buffer)
-            {
-                Ok(_) => (),
-                Err(e) => if e.kind() == io::ErrorKind::UnexpectedEof { break } // We have reached end of PNG buffer
-            }
-            let data_length = u32::from_be_bytes(data_length_buffer);
-
-            let mut chunk_type_buffer: [u8; 4] = [0; 4];
-            reader.read_exact(&mut chunk_type_buffer)?;
-
-            let mut data_buffer = vec![0u8; data_length as usize];
-            reader.read_exact(&mut data_buffer)?;
-
-            let mut crc_buffer: [u8; 4] = [0; 4];
-            reader.read_exact(&mut crc_buffer)?;
-
-            let whole: Vec<u8> = data_length_buffer
-                .iter()
-                .cloned()
-                .chain(chunk_type_buffer
-                    .iter()
-                    .cloned())
-                .chain(data_buffer
-                    .iter()
-                    .cloned())
-                .chain(crc_buffer
-                    .iter()
-                    .cloned())
-                .collect();
-
-            let chunk = Chunk::try_from(&whole[..])?;
-            chunks.push(chunk)
+            bail!("Expected exactly one IHDR chunk, found {}", count(ImageHeader::CHUNK_TYPE));
+        }
+        if count("IDAT") == 0
+        {
+            bail!("Missing IDAT chunk");
         }
 
-        Ok(Png::from_chunks(chunks))
+        match count("IEND")
+        {
+            0 => chunks.push(Chunk::new(ChunkType::new(*b"IEND")?, vec![])),
+            1 if chunks[chunks.len() - 1].chunk_type().to_string() == "IEND" => (),
+            _ => bail!("IEND must be the last chunk"),
+        }
+
+        let mut png = Png::new(chunks);
+        png.image_header()?;
+        png.validate_animation()?;
+        png.set_trailer(self.trailer);
+
+        Ok(png)
+    }
+}
+
+
+impl<'a> IntoIterator for &'a Png
+{
+    type Item = &'a Chunk;
+    type IntoIter = core::slice::Iter<'a, Chunk>;
+
+    fn into_iter(self) -> Self::IntoIter
+    {
+        self.iter()
+    }
+}
+
+
+impl TryFrom<&[u8]> for Png
+{
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self>
+    {
+        Ok(PngRef::try_from(bytes)?.to_png())
     }
 }
 
@@ -157,13 +368,33 @@ impl fmt::Display for Png
             writeln!(f, "{chunk}")?;
         }
 
+        if !self.trailer.is_empty()
+        {
+            writeln!(f, "Trailer: {} bytes after IEND", self.trailer.len())?;
+            writeln!(f)?;
+        }
+
+        if let Ok(Some(control)) = self.animation_control()
+        {
+            writeln!(f, "{control}")?;
+
+            match self.frames()
+            {
+                Ok(frames) => for frame in frames
+                {
+                    writeln!(f, "{frame}")?;
+                },
+                Err(e) => writeln!(f, "Invalid animation: {e}")?,
+            }
+        }
+
         Ok(())
     }
 }
 
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
     use crate::chunk::Chunk;
@@ -171,13 +402,11 @@ mod tests {
     use std::convert::TryFrom;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -295,6 +524,34 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_append_chunk_before_iend() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("IEND", "").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["FrSt", "miDl", "LASt", "TeSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.insert_chunk(1, chunk_from_strings("TeSt", "Message").unwrap());
+        png.insert_chunk(4, chunk_from_strings("IEND", "").unwrap());
+        let types: Vec<String> = png.chunks().iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["FrSt", "TeSt", "miDl", "LASt", "IEND"]);
+    }
+
+    #[test]
+    fn test_animation() {
+        let png = Png::from_chunks(crate::apng::tests::testing_apng_chunks());
+        assert!(png.is_animated());
+        assert_eq!(png.animation_control().unwrap().unwrap().num_frames, 2);
+        assert_eq!(png.frames().unwrap().len(), 2);
+        assert!(png.validate_animation().is_ok());
+        assert!(!testing_png().is_animated());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -305,10 +562,95 @@ mod tests {
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
-        let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
         assert_eq!(actual, expected);
     }
 
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_write_to() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut bytes = vec![];
+        png.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes, PNG_FILE.to_vec());
+        assert_eq!(png.byte_len(), PNG_FILE.len());
+    }
+
+    #[test]
+    fn test_invalid_crc_round_trip() {
+        let mut bytes = PNG_FILE.to_vec();
+        // Corrupt the CRC of IHDR
+        bytes[29] ^= 0xff;
+
+        assert!(Png::try_from(&bytes[..]).is_err());
+
+        let mut png = Png::try_from_unchecked(&bytes[..]).unwrap();
+        assert_eq!(png.as_bytes(), bytes);
+
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(png.as_bytes()[..33], bytes[..33]);
+        png.remove_chunk("TeSt").unwrap();
+
+        let repaired = png.repair_crcs();
+        assert_eq!(repaired.len(), 1);
+        assert_eq!(repaired[0].0, 0);
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_trailer() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"PK\x03\x04 not a chunk");
+
+        let mut png = Png::try_from(&bytes[..]).unwrap();
+        assert_eq!(png.trailer(), b"PK\x03\x04 not a chunk");
+        assert_eq!(png.as_bytes(), bytes);
+        assert!(png.to_string().contains("Trailer: 16 bytes"));
+
+        assert_eq!(png.take_trailer(), b"PK\x03\x04 not a chunk");
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_no_trailer() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(png.trailer().is_empty());
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut png = testing_png();
+        let types: Vec<String> = (&png).into_iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["FrSt", "miDl", "LASt"]);
+        assert_eq!(png.iter().count(), png.chunks().len());
+
+        png.chunks_mut()[1] = Chunk::new(ChunkType::new(*b"ruSt").unwrap(), vec![1]);
+        assert!(png.chunk_by_type("ruSt").is_some());
+    }
+
+    #[test]
+    fn test_builder() {
+        let image = Png::try_from(&PNG_FILE[..]).unwrap();
+        let idat = image.chunk_by_type("IDAT").unwrap().clone();
+        let text = Chunk::new(ChunkType::new(*b"tEXt").unwrap(), b"a\0b".to_vec());
+
+        let png = Png::builder()
+            .header(image.image_header().unwrap())
+            .chunk(idat.clone())
+            .chunk(text.clone())
+            .trailer(b"end".to_vec())
+            .build()
+            .unwrap();
+        let types: Vec<String> = png.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, ["IHDR", "IDAT", "tEXt", "IEND"]);
+        assert_eq!(png.trailer(), b"end");
+
+        assert!(Png::builder().chunk(idat.clone()).build().is_err());
+        assert!(Png::builder().header(image.image_header().unwrap()).chunk(text).build().is_err());
+        let iend = image.chunk_by_type("IEND").unwrap().clone();
+        assert!(Png::builder().header(image.image_header().unwrap()).chunks([iend, idat]).build().is_err());
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -328,7 +670,7 @@ mod tests {
     }
 
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
-    const PNG_FILE: [u8; 4803] = [
+    pub(crate) const PNG_FILE: [u8; 4803] = [
         137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
         6, 0, 0, 0, 30, 63, 136, 177, 0, 0, 0, 1, 115, 82, 71, 66, 0, 174, 206, 28, 233, 0, 0, 0,
         4, 103, 65, 77, 65, 0, 0, 177, 143, 11, 252, 97, 5, 0, 0, 0, 9, 112, 72, 89, 115, 0, 0, 14,
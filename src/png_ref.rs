@@ -0,0 +1,154 @@
+use crate::chunk_ref::ChunkRef;
+use crate::png::Png;
+
+use crate::error::{Error, Result};
+
+use alloc::string::ToString;
+use alloc::vec::Vec;
+use alloc::vec;
+
+use core::fmt;
+
+
+/// A PNG borrowed from its bytes, e.g. a memory mapped file. Parsing does not copy chunk data
+#[derive(Debug, Clone)]
+pub struct PngRef<'a>
+{
+    chunks: Vec<ChunkRef<'a>>,
+    trailer: &'a [u8],
+}
+
+
+impl<'a> PngRef<'a>
+{
+    pub fn chunks(&self) -> &[ChunkRef<'a>]
+    {
+        &self.chunks
+    }
+
+    pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&ChunkRef<'a>>
+    {
+        self.chunks
+            .iter()
+            .find(|e| e.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Bytes found after the `IEND` chunk
+    pub fn trailer(&self) -> &'a [u8]
+    {
+        self.trailer
+    }
+
+    /// Copies the chunks into an owned `Png` that can be modified
+    pub fn to_png(&self) -> Png
+    {
+        let mut png = Png::from_chunks(self.chunks.iter().map(ChunkRef::to_chunk).collect());
+        png.set_trailer(self.trailer.to_vec());
+        png
+    }
+
+    /// Like `try_from`, but accepts chunks whose stored CRC does not match their contents
+    pub fn try_from_unchecked(bytes: &'a [u8]) -> Result<Self>
+    {
+        Self::parse(bytes, false)
+    }
+
+    fn parse(bytes: &'a [u8], verify_crc: bool) -> Result<Self>
+    {
+        Png::check_header(bytes)?;
+
+        let mut chunks = vec![];
+        let mut offset = Png::STANDARD_HEADER.len();
+
+        while offset < bytes.len()
+        {
+            let (chunk, used) = if verify_crc {
+                ChunkRef::parse(&bytes[offset..])?
+            } else {
+                ChunkRef::parse_unchecked(&bytes[offset..])?
+            };
+            let is_end = chunk.chunk_type().to_string() == "IEND";
+
+            chunks.push(chunk);
+            offset += used;
+
+            if is_end
+            {
+                break;
+            }
+        }
+
+        Ok(Self {chunks, trailer: &bytes[offset..]})
+    }
+}
+
+
+impl<'a> TryFrom<&'a [u8]> for PngRef<'a>
+{
+    type Error = Error;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self>
+    {
+        Self::parse(bytes, true)
+    }
+}
+
+
+impl fmt::Display for PngRef<'_>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        for chunk in &self.chunks
+        {
+            writeln!(f, "{chunk}")?;
+        }
+
+        if !self.trailer.is_empty()
+        {
+            writeln!(f, "Trailer: {} bytes after IEND", self.trailer.len())?;
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+
+    #[test]
+    fn test_png_ref_from_bytes() {
+        let png = PngRef::try_from(&PNG_FILE[..]).unwrap();
+        let owned = Png::try_from(&PNG_FILE[..]).unwrap();
+
+        assert_eq!(png.chunks().len(), owned.chunks().len());
+        assert_eq!(png.to_png().as_bytes(), PNG_FILE.to_vec());
+        assert!(png.trailer().is_empty());
+    }
+
+    #[test]
+    fn test_png_ref_borrows() {
+        let png = PngRef::try_from(&PNG_FILE[..]).unwrap();
+        let header = png.chunk_by_type("IHDR").unwrap();
+        assert_eq!(header.data().as_ptr(), PNG_FILE[16..].as_ptr());
+    }
+
+    #[test]
+    fn test_png_ref_trailer() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"appended");
+
+        let png = PngRef::try_from(&bytes[..]).unwrap();
+        assert_eq!(png.trailer(), b"appended");
+        assert_eq!(png.to_png().as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_png_ref_invalid() {
+        assert!(PngRef::try_from(&PNG_FILE[1..]).is_err());
+        assert!(PngRef::try_from(&PNG_FILE[..100]).is_err());
+    }
+}
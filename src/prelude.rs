@@ -0,0 +1,8 @@
+//! The types needed for most uses of the library, `use pngme::prelude::*`
+
+pub use crate::chunk::Chunk;
+pub use crate::chunk_ref::ChunkRef;
+pub use crate::chunk_type::ChunkType;
+pub use crate::ihdr::ImageHeader;
+pub use crate::png::{Png, PngBuilder};
+pub use crate::png_ref::PngRef;
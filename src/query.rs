@@ -0,0 +1,191 @@
+use crate::chunk::{self, Chunk, StreamingCrc};
+use crate::chunk_type::ChunkType;
+use crate::error::Error;
+use crate::ihdr::ImageHeader;
+use crate::png::Png;
+
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+
+
+/// How much of the skipped chunks a query reads
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions
+{
+    /// Read the data of skipped chunks to check their CRC instead of seeking past it
+    pub verify_skipped: bool,
+}
+
+
+fn invalid_data(error: Error) -> io::Error
+{
+    io::Error::new(ErrorKind::InvalidData, error)
+}
+
+
+fn read_signature<R: Read>(reader: &mut R) -> io::Result<()>
+{
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+    Png::check_header(&header).map_err(invalid_data)
+}
+
+
+/// Reads the length and type that start a chunk
+fn read_chunk_head<R: Read>(reader: &mut R) -> io::Result<(usize, ChunkType, [u8; 8])>
+{
+    let mut head = [0; 8];
+    reader.read_exact(&mut head)?;
+
+    let (length, chunk_type) = chunk::parse_head(&head, Chunk::MAX_LENGTH).map_err(invalid_data)?;
+    Ok((length, chunk_type, head))
+}
+
+
+/// Reads the rest of a chunk whose head was read and checks it like `Chunk::try_from`
+fn read_chunk_body<R: Read>(reader: &mut R, length: usize, head: [u8; 8]) -> io::Result<Chunk>
+{
+    let mut bytes = Vec::with_capacity(Chunk::OVERHEAD);
+    bytes.extend_from_slice(&head);
+    reader.take(length as u64 + 4).read_to_end(&mut bytes)?;
+
+    if bytes.len() != length + Chunk::OVERHEAD
+    {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+
+    Chunk::try_from(&bytes[..]).map_err(invalid_data)
+}
+
+
+/// Moves past the data and CRC of a chunk whose head was read
+fn skip_chunk_body<R: Read + Seek>(reader: &mut R, length: usize, chunk_type: ChunkType, options: &QueryOptions) -> io::Result<()>
+{
+    if !options.verify_skipped
+    {
+        reader.seek(SeekFrom::Current(length as i64 + 4))?;
+        return Ok(());
+    }
+
+    let mut crc = StreamingCrc::new(chunk_type);
+    let mut buffer = [0; chunk::SKIP_BUFFER];
+    let mut remaining = length;
+    while remaining > 0
+    {
+        let piece = &mut buffer[..remaining.min(chunk::SKIP_BUFFER)];
+        reader.read_exact(piece)?;
+        crc.update(piece);
+        remaining -= piece.len();
+    }
+
+    let mut stored = [0; 4];
+    reader.read_exact(&mut stored)?;
+    crc.verify(stored).map_err(invalid_data)
+}
+
+
+/// Returns the first chunk of `chunk_type`, seeking past the data of the chunks before it
+/// without checking their CRC. Returns `None` if `IEND` comes first
+pub fn find_chunk<R: Read + Seek>(reader: &mut R, chunk_type: &str) -> io::Result<Option<Chunk>>
+{
+    find_chunk_with(reader, chunk_type, &QueryOptions::default())
+}
+
+
+/// Like `find_chunk`, with control over how skipped chunks are checked
+pub fn find_chunk_with<R: Read + Seek>(reader: &mut R, chunk_type: &str, options: &QueryOptions) -> io::Result<Option<Chunk>>
+{
+    let wanted: ChunkType = chunk_type.parse().map_err(invalid_data)?;
+    read_signature(reader)?;
+
+    loop
+    {
+        let (length, found, head) = read_chunk_head(reader)?;
+
+        if found == wanted
+        {
+            return read_chunk_body(reader, length, head).map(Some);
+        }
+        if found.bytes() == *b"IEND"
+        {
+            return Ok(None);
+        }
+
+        skip_chunk_body(reader, length, found, options)?;
+    }
+}
+
+
+/// Reads the signature and the `IHDR` chunk, which must come first, and nothing after it
+pub fn read_header<R: Read>(reader: &mut R) -> io::Result<ImageHeader>
+{
+    read_signature(reader)?;
+
+    let (length, _, head) = read_chunk_head(reader)?;
+    if length != ImageHeader::LENGTH
+    {
+        return Err(invalid_data(Error::Malformed(format!("Expected IHDR chunk of {} bytes, found {length} bytes",
+            ImageHeader::LENGTH))));
+    }
+
+    let chunk = read_chunk_body(reader, length, head)?;
+    ImageHeader::try_from(&chunk).map_err(invalid_data)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+    use std::io::Cursor;
+
+    fn expected() -> Png {
+        Png::try_from(&PNG_FILE[..]).unwrap()
+    }
+
+    #[test]
+    fn test_read_header() {
+        // Everything after IHDR is garbage that must not be read
+        let mut bytes = PNG_FILE[..33].to_vec();
+        bytes.extend_from_slice(&[0xff; 64]);
+
+        let mut reader = Cursor::new(bytes);
+        assert_eq!(read_header(&mut reader).unwrap(), expected().image_header().unwrap());
+        assert_eq!(reader.position(), 33);
+
+        assert!(read_header(&mut Cursor::new(&PNG_FILE[1..])).is_err());
+        assert!(read_header(&mut Cursor::new(&PNG_FILE[..20])).is_err());
+    }
+
+    #[test]
+    fn test_find_chunk() {
+        let mut reader = Cursor::new(&PNG_FILE[..]);
+        let chunk = find_chunk(&mut reader, "IEND").unwrap().unwrap();
+        assert_eq!(chunk.as_bytes(), expected().chunk_by_type("IEND").unwrap().as_bytes());
+        assert_eq!(reader.position() as usize, PNG_FILE.len());
+
+        assert!(find_chunk(&mut Cursor::new(&PNG_FILE[..]), "ruSt").unwrap().is_none());
+        assert!(find_chunk(&mut Cursor::new(&PNG_FILE[..]), "ru5t").is_err());
+    }
+
+    #[test]
+    fn test_find_chunk_skips_without_verifying() {
+        // Corrupt the first IDAT, which is skipped on the way to IEND
+        let png = expected();
+        let offset = 8 + png.chunks().iter().take_while(|c| c.chunk_type().to_string() != "IDAT").map(Chunk::byte_len).sum::<usize>();
+        let mut bytes = PNG_FILE.to_vec();
+        bytes[offset + 8] ^= 1;
+
+        assert!(find_chunk(&mut Cursor::new(&bytes), "IEND").unwrap().is_some());
+
+        let options = QueryOptions {verify_skipped: true};
+        let error = find_chunk_with(&mut Cursor::new(&bytes), "IEND", &options).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(find_chunk(&mut Cursor::new(&bytes), "IDAT").is_err());
+    }
+
+    #[test]
+    fn test_find_chunk_truncated() {
+        let error = find_chunk(&mut Cursor::new(&PNG_FILE[..PNG_FILE.len() - 20]), "ruSt").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+    }
+}
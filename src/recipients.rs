@@ -0,0 +1,132 @@
+use anyhow::{Context, Result, bail};
+use age::secrecy::ExposeSecret;
+use zeroize::Zeroizing;
+
+use std::io::prelude::*;
+use std::str::FromStr;
+
+pub use age::x25519::{Identity, Recipient};
+
+
+/// Parses a public key such as `age1...`
+pub fn parse_recipient(recipient: &str) -> Result<Recipient>
+{
+    match Recipient::from_str(recipient.trim())
+    {
+        Ok(recipient) => Ok(recipient),
+        Err(_) => bail!("Invalid recipient {recipient:?}, expected an age1... public key"),
+    }
+}
+
+
+/// Parses the identities of an identity file, one `AGE-SECRET-KEY-1...` per line.
+/// Empty lines and lines starting with `#` are skipped
+pub fn parse_identities(contents: &str) -> Result<Vec<Identity>>
+{
+    let mut identities = vec![];
+    for (number, line) in contents.lines().enumerate()
+    {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#')
+        {
+            continue;
+        }
+
+        match Identity::from_str(line)
+        {
+            Ok(identity) => identities.push(identity),
+            Err(_) => bail!("Invalid identity on line {}", number + 1),
+        }
+    }
+
+    if identities.is_empty()
+    {
+        bail!("No identities found");
+    }
+
+    Ok(identities)
+}
+
+
+/// The identity file of a new identity, in the format of `age-keygen`
+pub fn generate_identity() -> (Zeroizing<String>, Recipient)
+{
+    let identity = Identity::generate();
+    let recipient = identity.to_public();
+
+    let file = format!("# public key: {recipient}\n{}\n", identity.to_string().expose_secret());
+    (Zeroizing::new(file), recipient)
+}
+
+
+/// Encrypts a message in the age format so that any one of `recipients` can decrypt it
+pub fn encrypt(recipients: &[Recipient], message: &[u8]) -> Result<Vec<u8>>
+{
+    let encryptor = age::Encryptor::with_recipients(recipients.iter().map(|recipient| recipient as _))
+        .context("No recipients to encrypt the message for")?;
+
+    let mut encrypted = vec![];
+    let mut writer = encryptor.wrap_output(&mut encrypted)?;
+    writer.write_all(message)?;
+    writer.finish()?;
+
+    Ok(encrypted)
+}
+
+
+/// Decrypts a message encrypted by `encrypt` with the identity of one of its recipients
+pub fn decrypt(identities: &[Identity], encrypted: &[u8]) -> Result<Zeroizing<Vec<u8>>>
+{
+    let decryptor = age::Decryptor::new(encrypted).context("The message is not encrypted for recipients")?;
+    let mut reader = decryptor.decrypt(identities.iter().map(|identity| identity as _))
+        .context("Failed to decrypt message. Maybe it was not encrypted for these identities?")?;
+
+    let mut message = Zeroizing::new(vec![]);
+    reader.read_to_end(&mut message)?;
+
+    Ok(message)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let (alice, alice_public) = generate_identity();
+        let (bob, bob_public) = generate_identity();
+        let (eve, _) = generate_identity();
+
+        let encrypted = encrypt(&[alice_public, bob_public], b"For your eyes only").unwrap();
+        assert!(encrypted.starts_with(b"age-encryption.org/v1\n"));
+
+        for identity in [alice, bob] {
+            let message = decrypt(&parse_identities(&identity).unwrap(), &encrypted).unwrap();
+            assert_eq!(&message[..], b"For your eyes only");
+        }
+        assert!(decrypt(&parse_identities(&eve).unwrap(), &encrypted).is_err());
+        assert!(encrypt(&[], b"nobody").is_err());
+    }
+
+    #[test]
+    fn test_parse_recipient() {
+        let (_, recipient) = generate_identity();
+        let parsed = parse_recipient(&format!(" {recipient}\n")).unwrap();
+        assert_eq!(parsed.to_string(), recipient.to_string());
+        assert!(parse_recipient("age1notakey").is_err());
+    }
+
+    #[test]
+    fn test_parse_identities() {
+        let (identity, recipient) = generate_identity();
+        assert!(identity.starts_with(&format!("# public key: {recipient}\n")));
+
+        let both = format!("{}\n\n{}", *identity, *generate_identity().0);
+        assert_eq!(parse_identities(&both).unwrap().len(), 2);
+        assert_eq!(parse_identities(&identity).unwrap()[0].to_public().to_string(), recipient.to_string());
+
+        assert!(parse_identities("# only a comment\n").is_err());
+        assert!(parse_identities("AGE-SECRET-KEY-1BROKEN\n").is_err());
+    }
+}
@@ -0,0 +1,280 @@
+use crate::chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+use crate::error::{Result, bail};
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::vec;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use core::fmt;
+
+
+/// What kind of irregularity a finding reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum FindingKind
+{
+    /// A chunk with the private bit set
+    PrivateChunk,
+    /// A public chunk that is not a registered PNG chunk type
+    UnknownChunk,
+    /// A well formed chunk located after `IEND`
+    ChunkAfterIend,
+    /// Bytes after the PNG stream that do not form chunks
+    AppendedData,
+    /// An ancillary chunk larger than the configured limit
+    SuspiciousSize,
+    /// A chunk whose stored CRC does not match its contents
+    InvalidCrc,
+    /// The stream could not be walked any further
+    Malformed,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Finding
+{
+    pub kind: FindingKind,
+    /// Byte offset of the chunk or data in the file
+    pub offset: usize,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub chunk_type: Option<String>,
+    pub length: usize,
+    pub message: String,
+}
+
+
+/// Limits used to flag suspicious chunks
+#[derive(Debug, Clone)]
+pub struct ScanOptions
+{
+    pub max_ancillary_size: usize,
+}
+
+
+impl Default for ScanOptions
+{
+    fn default() -> Self
+    {
+        Self {max_ancillary_size: 64 * 1024}
+    }
+}
+
+
+/// Overall outcome of a scan, mapped to the process exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status
+{
+    Clean = 0,
+    Suspicious = 1,
+    Failed = 2,
+}
+
+
+impl Finding
+{
+    fn new(kind: FindingKind, offset: usize, chunk_type: Option<String>, length: usize, message: String) -> Self
+    {
+        Self {kind, offset, chunk_type, length, message}
+    }
+}
+
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32
+{
+    u32::from_be_bytes(bytes[offset..offset + 4].try_into().expect("Slice of 4 bytes"))
+}
+
+
+/// Walks the raw chunk stream without stopping at errors and reports anything that
+/// could carry hidden data. Fails only if the file is not a PNG at all
+pub fn scan(bytes: &[u8], options: &ScanOptions) -> Result<Vec<Finding>>
+{
+    if bytes.len() < Png::STANDARD_HEADER.len() || bytes[..8] != Png::STANDARD_HEADER
+    {
+        bail!("Not a PNG file");
+    }
+
+    let mut findings = vec![];
+    let mut offset = Png::STANDARD_HEADER.len();
+    let mut after_iend = false;
+
+    while offset < bytes.len()
+    {
+        let remaining = bytes.len() - offset;
+        let type_bytes: Option<[u8; 4]> = bytes.get(offset + 4..offset + 8).map(|b| b.try_into().expect("Slice of 4 bytes"));
+        let chunk_type = type_bytes
+            .filter(|b| b.iter().all(|&c| ChunkType::is_valid_byte(c)))
+            .and_then(|b| ChunkType::try_from(b).ok());
+
+        let chunk_type = match chunk_type
+        {
+            Some(chunk_type) if remaining >= 12 => chunk_type,
+            _ => {
+                let (kind, message) = if after_iend {
+                    (FindingKind::AppendedData, format!("{remaining} bytes appended after the PNG stream"))
+                } else {
+                    (FindingKind::Malformed, format!("{remaining} bytes that are not a chunk"))
+                };
+                findings.push(Finding::new(kind, offset, None, remaining, message));
+                break;
+            }
+        };
+
+        let length = read_u32(bytes, offset) as usize;
+        let name = chunk_type.to_string();
+
+        if length > remaining - 12
+        {
+            let (kind, message) = if after_iend {
+                (FindingKind::AppendedData, format!("{remaining} bytes appended after the PNG stream"))
+            } else {
+                (FindingKind::Malformed, format!("{name} declares {length} bytes but only {} remain", remaining - 12))
+            };
+            findings.push(Finding::new(kind, offset, Some(name), remaining, message));
+            break;
+        }
+
+        let data = &bytes[offset + 8..offset + 8 + length];
+        let stored_crc = read_u32(bytes, offset + 8 + length);
+
+        if after_iend
+        {
+            findings.push(Finding::new(FindingKind::ChunkAfterIend, offset, Some(name.clone()), length,
+                format!("{name} chunk after IEND")));
+        }
+
+        if !chunk_type.is_standard()
+        {
+            let (kind, message) = if chunk_type.is_public() {
+                (FindingKind::UnknownChunk, format!("Unknown chunk {name}"))
+            } else {
+                (FindingKind::PrivateChunk, format!("Private chunk {name}"))
+            };
+            findings.push(Finding::new(kind, offset, Some(name.clone()), length, message));
+        }
+
+        if !chunk_type.is_critical() && length > options.max_ancillary_size
+        {
+            findings.push(Finding::new(FindingKind::SuspiciousSize, offset, Some(name.clone()), length,
+                format!("Ancillary chunk {name} of {length} bytes exceeds {} bytes", options.max_ancillary_size)));
+        }
+
+        if chunk::checksum(&chunk_type, data) != stored_crc
+        {
+            findings.push(Finding::new(FindingKind::InvalidCrc, offset, Some(name.clone()), length,
+                format!("Invalid CRC in {name}")));
+        }
+
+        if name == "IEND"
+        {
+            after_iend = true;
+        }
+
+        offset += length + 12;
+    }
+
+    Ok(findings)
+}
+
+
+impl Status
+{
+    pub fn of(findings: &[Finding]) -> Self
+    {
+        if findings.is_empty() { Self::Clean } else { Self::Suspicious }
+    }
+
+    pub fn exit_code(&self) -> i32
+    {
+        *self as i32
+    }
+}
+
+
+impl fmt::Display for Finding
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "offset {}: {}", self.offset, self.message)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use std::str::FromStr;
+
+    fn chunk_bytes(chunk_type: &str, data: &[u8]) -> Vec<u8> {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).as_bytes()
+    }
+
+    fn testing_bytes(chunks: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut bytes = Png::STANDARD_HEADER.to_vec();
+        for (chunk_type, data) in chunks {
+            bytes.extend(chunk_bytes(chunk_type, data));
+        }
+        bytes
+    }
+
+    fn kinds(findings: &[Finding]) -> Vec<FindingKind> {
+        findings.iter().map(|f| f.kind).collect()
+    }
+
+    #[test]
+    fn test_clean() {
+        let bytes = testing_bytes(&[("IHDR", &[0; 13]), ("tEXt", b"a\0b"), ("acTL", &[0; 8]), ("IEND", &[])]);
+        let findings = scan(&bytes, &ScanOptions::default()).unwrap();
+        assert!(findings.is_empty());
+        assert_eq!(Status::of(&findings), Status::Clean);
+    }
+
+    #[test]
+    fn test_private_and_unknown() {
+        let bytes = testing_bytes(&[("IHDR", &[0; 13]), ("ruSt", b"x"), ("aBCd", b"y"), ("IEND", &[])]);
+        let findings = scan(&bytes, &ScanOptions::default()).unwrap();
+        assert_eq!(kinds(&findings), vec![FindingKind::PrivateChunk, FindingKind::UnknownChunk]);
+        assert_eq!(findings[0].offset, 33);
+        assert_eq!(Status::of(&findings).exit_code(), 1);
+    }
+
+    #[test]
+    fn test_after_iend() {
+        let mut bytes = testing_bytes(&[("IHDR", &[0; 13]), ("IEND", &[]), ("tEXt", b"a\0b")]);
+        bytes.extend(b"PK\x03\x04 zip data");
+        let findings = scan(&bytes, &ScanOptions::default()).unwrap();
+        assert_eq!(kinds(&findings), vec![FindingKind::ChunkAfterIend, FindingKind::AppendedData]);
+        assert_eq!(findings[1].length, 13);
+    }
+
+    #[test]
+    fn test_suspicious_size_and_crc() {
+        let mut bytes = testing_bytes(&[("IHDR", &[0; 13]), ("tEXt", &[0; 100]), ("IEND", &[])]);
+        let last = bytes.len() - 13;
+        bytes[last] ^= 1;
+        let findings = scan(&bytes, &ScanOptions {max_ancillary_size: 50}).unwrap();
+        assert_eq!(kinds(&findings), vec![FindingKind::SuspiciousSize, FindingKind::InvalidCrc]);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut bytes = testing_bytes(&[("IHDR", &[0; 13])]);
+        bytes.extend(chunk_bytes("IDAT", &[0; 20]));
+        bytes.truncate(bytes.len() - 10);
+        let findings = scan(&bytes, &ScanOptions::default()).unwrap();
+        assert_eq!(kinds(&findings), vec![FindingKind::Malformed]);
+    }
+
+    #[test]
+    fn test_not_png() {
+        assert!(scan(b"GIF89a", &ScanOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,272 @@
+use crate::chunk::{self, Chunk};
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, SeqAccess, Visitor};
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use core::fmt;
+use core::str::FromStr;
+
+
+/// Bytes written as base64 in human readable formats such as JSON, and as raw bytes otherwise
+struct Data<'a>(&'a [u8]);
+
+
+/// The owned counterpart of `Data`
+struct DataBuf(Vec<u8>);
+
+
+struct DataVisitor;
+
+
+/// A chunk as it is serialized. The CRC is the one stored in the file,
+/// so that chunks with a wrong CRC survive a round trip unchanged
+#[derive(Serialize)]
+#[serde(rename = "Chunk")]
+struct ChunkFields<'a>
+{
+    #[serde(rename = "type")]
+    chunk_type: &'a ChunkType,
+    data: Data<'a>,
+    crc: Option<u32>,
+}
+
+
+/// A deserialized chunk. Without a CRC, the one of the type and data is used
+#[derive(Deserialize)]
+#[serde(rename = "Chunk", deny_unknown_fields)]
+struct ChunkFieldsBuf
+{
+    #[serde(rename = "type")]
+    chunk_type: ChunkType,
+    data: DataBuf,
+    crc: Option<u32>,
+}
+
+
+#[derive(Serialize)]
+#[serde(rename = "Png")]
+struct PngFields<'a>
+{
+    chunks: &'a [Chunk],
+    trailer: Option<Data<'a>>,
+}
+
+
+#[derive(Deserialize)]
+#[serde(rename = "Png", deny_unknown_fields)]
+struct PngFieldsBuf
+{
+    chunks: Vec<Chunk>,
+    #[serde(default)]
+    trailer: Option<DataBuf>,
+}
+
+
+impl Serialize for Data<'_>
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        if serializer.is_human_readable()
+        {
+            serializer.serialize_str(&BASE64.encode(self.0))
+        }
+        else
+        {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+}
+
+
+impl<'de> Visitor<'de> for DataVisitor
+{
+    type Value = DataBuf;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "base64 encoded data or bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<DataBuf, E>
+    {
+        BASE64.decode(value).map(DataBuf).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<DataBuf, E>
+    {
+        Ok(DataBuf(value.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<DataBuf, E>
+    {
+        Ok(DataBuf(value))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<DataBuf, A::Error>
+    {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()?
+        {
+            bytes.push(byte);
+        }
+        Ok(DataBuf(bytes))
+    }
+}
+
+
+impl<'de> Deserialize<'de> for DataBuf
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        if deserializer.is_human_readable()
+        {
+            deserializer.deserialize_str(DataVisitor)
+        }
+        else
+        {
+            deserializer.deserialize_byte_buf(DataVisitor)
+        }
+    }
+}
+
+
+/// Serialized as its four letter string, e.g. `"IHDR"`
+impl Serialize for ChunkType
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+
+impl<'de> Deserialize<'de> for ChunkType
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        let name = String::deserialize(deserializer)?;
+        ChunkType::from_str(&name).map_err(de::Error::custom)
+    }
+}
+
+
+/// Serialized as `{"type": "tEXt", "data": "<base64>", "crc": 1234}`
+impl Serialize for Chunk
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        ChunkFields {chunk_type: self.chunk_type(), data: Data(self.data()), crc: Some(self.stored_crc())}
+            .serialize(serializer)
+    }
+}
+
+
+impl<'de> Deserialize<'de> for Chunk
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        let fields = ChunkFieldsBuf::deserialize(deserializer)?;
+        let crc = chunk::checksum(&fields.chunk_type, &fields.data.0);
+
+        Ok(Chunk::from_parts(fields.chunk_type, fields.data.0, crc, fields.crc.unwrap_or(crc)))
+    }
+}
+
+
+/// Serialized as `{"chunks": [...], "trailer": "<base64>"}`, with a null trailer if there is none
+impl Serialize for Png
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    {
+        let trailer = Some(Data(self.trailer())).filter(|trailer| !trailer.0.is_empty());
+        PngFields {chunks: self.chunks(), trailer}.serialize(serializer)
+    }
+}
+
+
+impl<'de> Deserialize<'de> for Png
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        let fields = PngFieldsBuf::deserialize(deserializer)?;
+
+        let mut png = Png::from_chunks(fields.chunks);
+        if let Some(trailer) = fields.trailer
+        {
+            png.set_trailer(trailer.0);
+        }
+
+        Ok(png)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+
+    #[test]
+    fn test_chunk_type_json() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        assert_eq!(serde_json::to_string(&chunk_type).unwrap(), "\"RuSt\"");
+        assert_eq!(serde_json::from_str::<ChunkType>("\"RuSt\"").unwrap(), chunk_type);
+        assert!(serde_json::from_str::<ChunkType>("\"Ru1t\"").is_err());
+        assert!(serde_json::from_str::<ChunkType>("\"RuStic\"").is_err());
+    }
+
+    #[test]
+    fn test_chunk_json() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"hi".to_vec());
+        let json = serde_json::to_string(&chunk).unwrap();
+        assert_eq!(json, r#"{"type":"RuSt","data":"aGk=","crc":3535552371}"#);
+
+        let parsed: Chunk = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.as_bytes(), chunk.as_bytes());
+
+        let without_crc: Chunk = serde_json::from_str(r#"{"type":"RuSt","data":"aGk="}"#).unwrap();
+        assert_eq!(without_crc.as_bytes(), chunk.as_bytes());
+        assert!(serde_json::from_str::<Chunk>(r#"{"type":"RuSt","data":"not base64!"}"#).is_err());
+    }
+
+    #[test]
+    fn test_png_json_round_trip() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"trailer");
+        // Break the CRC of the last chunk before IEND so it must survive as stored
+        let crc_offset = bytes.len() - b"trailer".len() - 12 - 1;
+        bytes[crc_offset] ^= 1;
+
+        let json = serde_json::to_string(&Png::try_from_unchecked(&bytes).unwrap()).unwrap();
+        let png: Png = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(png.as_bytes(), bytes);
+        assert_eq!(png.trailer(), b"trailer");
+    }
+
+    #[test]
+    fn test_png_json_without_trailer() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let json = serde_json::to_value(&png).unwrap();
+        assert!(json["trailer"].is_null());
+        assert!(serde_json::from_str::<Png>(r#"{"chunks":[]}"#).is_ok());
+        assert_eq!(json["chunks"][0]["type"], "IHDR");
+    }
+
+    #[test]
+    fn test_png_binary_round_trip() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let encoded = bincode::serialize(&png).unwrap();
+        assert!(encoded.len() < PNG_FILE.len() * 2);
+
+        let decoded: Png = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.as_bytes(), PNG_FILE.to_vec());
+    }
+}
@@ -0,0 +1,313 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::manifest::{self, Change, Manifest};
+use crate::png::Png;
+
+use anyhow::{Context, Result, bail};
+use ed25519_dalek::{Signature, Signer};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use std::str::FromStr;
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+
+/// Type of the private chunk holding the signature, placed before `IEND`
+pub const CHUNK_TYPE: &str = "siGN";
+
+/// Version of the signature chunk layout
+const VERSION: u8 = 1;
+
+/// Prefix of the signed bytes, so that the signature cannot be reused for anything else
+const CONTEXT: &[u8] = b"pngme chunk signature v1\0";
+
+/// Prefix of the secret key line of a key file
+const SECRET_PREFIX: &str = "PNGME-ED25519-SECRET-";
+
+/// Short identifier of a public key: the first bytes of its SHA-256
+pub type KeyId = [u8; 8];
+
+
+/// Outcome of checking a signature that is valid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification
+{
+    pub key_id: KeyId,
+    /// Chunks added, removed, altered or moved after signing, empty if the PNG is unchanged
+    pub changes: Vec<Change>,
+}
+
+
+/// The contents of a signature chunk: version, key ID, signature and the signed manifest
+struct SignatureChunk
+{
+    key_id: KeyId,
+    signature: Signature,
+    manifest: Manifest,
+}
+
+
+impl SignatureChunk
+{
+    const HEADER_LENGTH: usize = 1 + 8 + Signature::BYTE_SIZE;
+
+    fn as_bytes(&self) -> Vec<u8>
+    {
+        let mut bytes = vec![VERSION];
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(&self.signature.to_bytes());
+        bytes.extend_from_slice(&self.manifest.as_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self>
+    {
+        if bytes.len() < Self::HEADER_LENGTH
+        {
+            bail!("Signature chunk of {} bytes is too short", bytes.len());
+        }
+        if bytes[0] != VERSION
+        {
+            bail!("Unsupported signature chunk version {}", bytes[0]);
+        }
+
+        Ok(Self {
+            key_id: bytes[1..9].try_into()?,
+            signature: Signature::from_bytes(bytes[9..Self::HEADER_LENGTH].try_into()?),
+            manifest: Manifest::from_bytes(&bytes[Self::HEADER_LENGTH..]).context("Invalid manifest in signature chunk")?,
+        })
+    }
+}
+
+
+/// The bytes covered by the signature
+fn signed_bytes(key_id: &KeyId, manifest: &Manifest) -> Vec<u8>
+{
+    let mut bytes = CONTEXT.to_vec();
+    bytes.extend_from_slice(key_id);
+    bytes.extend_from_slice(&manifest.as_bytes());
+    bytes
+}
+
+
+pub fn key_id(key: &VerifyingKey) -> KeyId
+{
+    Sha256::digest(key.as_bytes())[..8].try_into().unwrap()
+}
+
+
+/// The key ID as 16 hex digits
+pub fn key_id_string(key_id: &KeyId) -> String
+{
+    hex::encode(key_id)
+}
+
+
+/// The public key as 64 hex digits, as accepted by `parse_verifying_key`
+pub fn public_key_string(key: &VerifyingKey) -> String
+{
+    hex::encode(key.as_bytes())
+}
+
+
+/// A new signing key as a key file, and its public key
+pub fn generate_key() -> Result<(Zeroizing<String>, VerifyingKey)>
+{
+    let mut secret = Zeroizing::new([0; 32]);
+    getrandom::getrandom(&mut secret[..]).context("Failed to get random bytes for the key")?;
+
+    let key = SigningKey::from_bytes(&secret);
+    let public = key.verifying_key();
+    let secret_hex = Zeroizing::new(hex::encode(*secret));
+
+    let file = format!("# public key: {}\n{SECRET_PREFIX}{}\n", public_key_string(&public), *secret_hex);
+    Ok((Zeroizing::new(file), public))
+}
+
+
+/// Parses the secret key of a key file written by `generate_key`. Lines starting with `#` are skipped
+pub fn parse_signing_key(contents: &str) -> Result<SigningKey>
+{
+    let line = contents.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .context("No key found in key file")?;
+
+    let secret_hex = match line.strip_prefix(SECRET_PREFIX)
+    {
+        Some(secret_hex) => secret_hex,
+        None => bail!("Not a signing key file, expected a line starting with {SECRET_PREFIX}"),
+    };
+
+    let mut secret = Zeroizing::new([0; 32]);
+    hex::decode_to_slice(secret_hex, &mut secret[..]).context("Invalid signing key")?;
+
+    Ok(SigningKey::from_bytes(&secret))
+}
+
+
+/// Parses a public key of 64 hex digits
+pub fn parse_verifying_key(key: &str) -> Result<VerifyingKey>
+{
+    let mut bytes = [0; 32];
+    hex::decode_to_slice(key.trim(), &mut bytes).with_context(|| format!("Invalid public key {key:?}"))?;
+
+    VerifyingKey::from_bytes(&bytes).with_context(|| format!("Invalid public key {key:?}"))
+}
+
+
+/// Signs the digests of all chunks of `png` and stores the signature in a `siGN` chunk before `IEND`,
+/// replacing any previous signature
+pub fn sign(png: &mut Png, key: &SigningKey) -> Result<()>
+{
+    while png.remove_chunk(CHUNK_TYPE).is_ok() {}
+
+    let manifest = Manifest::of(png, CHUNK_TYPE);
+    let key_id = key_id(&key.verifying_key());
+    let signature = key.sign(&signed_bytes(&key_id, &manifest));
+
+    let data = SignatureChunk {key_id, signature, manifest}.as_bytes();
+    png.append_chunk(Chunk::new(ChunkType::from_str(CHUNK_TYPE)?, data));
+
+    Ok(())
+}
+
+
+/// Checks the signature of `png` with whichever of `keys` it names, and reports the chunks that
+/// changed since signing. A seal added after signing is not a change as long as it matches the PNG.
+/// Fails if the signature is missing, by an unknown key or invalid
+pub fn verify(png: &Png, keys: &[VerifyingKey]) -> Result<Verification>
+{
+    let mut chunks = png.chunks_by_type(CHUNK_TYPE);
+    let chunk = match (chunks.next(), chunks.next())
+    {
+        (Some(chunk), None) => chunk,
+        (None, _) => bail!("No {CHUNK_TYPE} signature chunk"),
+        (Some(_), Some(_)) => bail!("More than one {CHUNK_TYPE} signature chunk"),
+    };
+
+    let signed = SignatureChunk::from_bytes(chunk.data())?;
+    let key = match keys.iter().find(|key| key_id(key) == signed.key_id)
+    {
+        Some(key) => key,
+        None => bail!("Signed with key {}, which is not one of the given public keys", key_id_string(&signed.key_id)),
+    };
+
+    if key.verify_strict(&signed_bytes(&signed.key_id, &signed.manifest), &signed.signature).is_err()
+    {
+        bail!("Invalid signature by key {}", key_id_string(&signed.key_id));
+    }
+
+    let mut changes = signed.manifest.compare(&Manifest::of(png, CHUNK_TYPE));
+
+    // A seal added since signing that matches the PNG adds nothing beyond digests of signed chunks
+    let sealed_since = |change: &Change| {
+        matches!(change, Change::Added {chunk_type, ..} if chunk_type.bytes() == manifest::SEAL_CHUNK_TYPE.as_bytes())
+    };
+    if changes.iter().filter(|change| sealed_since(change)).count() == 1
+        && manifest::check(png).is_ok_and(|changes| changes.is_empty())
+    {
+        changes.retain(|change| !sealed_since(change));
+    }
+
+    Ok(Verification {key_id: signed.key_id, changes})
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+
+    fn signed_png(key: &SigningKey) -> Png {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        sign(&mut png, key).unwrap();
+        Png::try_from(&png.as_bytes()[..]).unwrap()
+    }
+
+    fn testing_key() -> SigningKey {
+        parse_signing_key(&generate_key().unwrap().0).unwrap()
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let key = testing_key();
+        let mut png = signed_png(&key);
+        assert_eq!(png.chunks().iter().rev().nth(1).unwrap().chunk_type().to_string(), CHUNK_TYPE);
+
+        let verification = verify(&png, &[testing_key().verifying_key(), key.verifying_key()]).unwrap();
+        assert_eq!(verification.key_id, key_id(&key.verifying_key()));
+        assert!(verification.changes.is_empty());
+
+        // Signing again replaces the signature
+        sign(&mut png, &key).unwrap();
+        assert_eq!(png.chunks_by_type(CHUNK_TYPE).count(), 1);
+        assert!(verify(&png, &[key.verifying_key()]).unwrap().changes.is_empty());
+    }
+
+    #[test]
+    fn test_verify_changes() {
+        let key = testing_key();
+        let mut png = signed_png(&key);
+        png.append_chunk(Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Comment\0added".to_vec()));
+
+        let changes = verify(&png, &[key.verifying_key()]).unwrap().changes;
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(&changes[0], Change::Added {chunk_type, ..} if chunk_type.to_string() == "tEXt"));
+    }
+
+    #[test]
+    fn test_verify_seal() {
+        let key = testing_key();
+
+        // Sealing after signing is fine, altering or forging that seal is not
+        let mut png = signed_png(&key);
+        manifest::seal(&mut png).unwrap();
+        assert!(verify(&png, &[key.verifying_key()]).unwrap().changes.is_empty());
+
+        let index = png.chunks().len() - 2;
+        png.chunks_mut()[index] = Chunk::new(ChunkType::from_str(manifest::SEAL_CHUNK_TYPE).unwrap(), b"\x01forged".to_vec());
+        let changes = verify(&png, &[key.verifying_key()]).unwrap().changes;
+        assert!(matches!(&changes[..], [Change::Added {chunk_type, ..}] if chunk_type.to_string() == "seAL"));
+
+        // A seal from before signing is signed like any other chunk
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        manifest::seal(&mut png).unwrap();
+        sign(&mut png, &key).unwrap();
+        let index = png.chunks().iter().position(|chunk| chunk.chunk_type().to_string() == "seAL").unwrap();
+        png.chunks_mut()[index] = Chunk::new(ChunkType::from_str(manifest::SEAL_CHUNK_TYPE).unwrap(), vec![1, 0, 0, 0, 0]);
+        let changes = verify(&png, &[key.verifying_key()]).unwrap().changes;
+        assert!(matches!(&changes[..], [Change::Altered {chunk_type, ..}] if chunk_type.to_string() == "seAL"));
+    }
+
+    #[test]
+    fn test_verify_failures() {
+        let key = testing_key();
+        let png = signed_png(&key);
+
+        assert!(verify(&Png::try_from(&PNG_FILE[..]).unwrap(), &[key.verifying_key()]).is_err());
+        assert!(verify(&png, &[testing_key().verifying_key()]).is_err());
+
+        // A forged manifest no longer matches the signature
+        let mut forged = Png::try_from(&png.as_bytes()[..]).unwrap();
+        let index = forged.chunks().len() - 2;
+        let mut data = forged.chunks()[index].data().to_vec();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        forged.chunks_mut()[index] = Chunk::new(ChunkType::from_str(CHUNK_TYPE).unwrap(), data);
+        assert!(verify(&forged, &[key.verifying_key()]).is_err());
+    }
+
+    #[test]
+    fn test_keys() {
+        let (file, public) = generate_key().unwrap();
+        assert_eq!(parse_signing_key(&file).unwrap().verifying_key(), public);
+        assert_eq!(parse_verifying_key(&public_key_string(&public)).unwrap(), public);
+        assert_eq!(key_id_string(&key_id(&public)).len(), 16);
+
+        assert!(parse_signing_key("# nothing here\n").is_err());
+        assert!(parse_signing_key("AGE-SECRET-KEY-1ABC\n").is_err());
+        assert!(parse_verifying_key("abcd").is_err());
+    }
+}
@@ -0,0 +1,416 @@
+use crate::chunk::Chunk;
+use crate::png::Png;
+use crate::pixels::{self, Image};
+
+use anyhow::{Result, bail};
+
+use std::fmt;
+
+
+/// The image is divided into `GRID` x `GRID` cells of equal share, whatever its size, so that
+/// the cells still line up after resizing
+const GRID: usize = 64;
+
+/// Bits of the ID, followed by as many check bits derived from the key and the ID
+const ID_BITS: usize = 32;
+const BITS: usize = ID_BITS * 2;
+
+/// z-score of the check bits above which a watermark counts as detected,
+/// about one false positive in 30000 unmarked images
+const THRESHOLD: f64 = 4.0;
+
+/// Ancillary chunks tied to the color type, bit depth or palette of the original image data,
+/// which no longer apply once it is encoded as 8-bit RGBA
+const DROPPED_CHUNK_TYPES: [&str; 5] = ["tRNS", "sBIT", "bKGD", "hIST", "sPLT"];
+
+
+/// How a watermark is embedded and looked for
+#[derive(Debug, Clone)]
+pub struct WatermarkOptions
+{
+    /// Secret that the pattern is derived from. Detection needs the same key
+    pub key: String,
+    /// Change of luminance in each cell, out of 255
+    pub strength: u8,
+}
+
+
+impl WatermarkOptions
+{
+    /// Change of luminance that survives re-encoding and resizing without being visible
+    pub const DEFAULT_STRENGTH: u8 = 3;
+
+    /// Options with the secret `key` and the default strength
+    pub fn new(key: impl Into<String>) -> Self
+    {
+        Self {key: key.into(), strength: Self::DEFAULT_STRENGTH}
+    }
+}
+
+
+/// Result of looking for a watermark
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection
+{
+    /// The ID read from the image, meaningless unless `detected`
+    pub id: u32,
+    /// How far the check bits are from chance, in standard deviations
+    pub z_score: f64,
+    /// Probability that the check bits did not match by chance, from 0 to 1
+    pub confidence: f64,
+    pub detected: bool,
+}
+
+
+/// splitmix64, seeded from the key
+struct Rng(u64);
+
+
+impl Rng
+{
+    fn new(key: &str, salt: u64) -> Self
+    {
+        // FNV-1a
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in key.bytes()
+        {
+            hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+        Self(hash ^ salt)
+    }
+
+    fn next(&mut self) -> u64
+    {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+
+/// The bit and chip (+1 or -1) of every cell, a keyed shuffle that spreads each bit over the image
+fn pattern(key: &str) -> Vec<(usize, f64)>
+{
+    let mut rng = Rng::new(key, 0);
+
+    let mut cells: Vec<usize> = (0..GRID * GRID).collect();
+    for i in (1..cells.len()).rev()
+    {
+        cells.swap(i, (rng.next() % (i as u64 + 1)) as usize);
+    }
+
+    let mut pattern = vec![(0, 0.0); GRID * GRID];
+    for (n, cell) in cells.into_iter().enumerate()
+    {
+        let chip = if rng.next() & 1 == 0 { 1.0 } else { -1.0 };
+        pattern[cell] = (n % BITS, chip);
+    }
+    pattern
+}
+
+
+/// The ID bits followed by the check bits, as +1 or -1
+fn payload(key: &str, id: u32) -> [f64; BITS]
+{
+    let check = Rng::new(key, id as u64 + 1).next() as u32;
+
+    let mut bits = [0.0; BITS];
+    for (i, bit) in bits.iter_mut().enumerate()
+    {
+        let value = if i < ID_BITS { id >> (ID_BITS - 1 - i) } else { check >> (BITS - 1 - i) };
+        *bit = if value & 1 == 1 { 1.0 } else { -1.0 };
+    }
+    bits
+}
+
+
+/// The range of pixels covered by cell `index` of a side of `length` pixels
+fn cell_range(index: usize, length: u32) -> std::ops::Range<u32>
+{
+    let length = length as usize;
+    (index * length / GRID) as u32..((index + 1) * length / GRID) as u32
+}
+
+
+fn check_size(image: &Image) -> Result<()>
+{
+    if (image.width as usize) < GRID || (image.height as usize) < GRID
+    {
+        bail!("Image of {}x{} pixels is too small for a watermark, at least {GRID}x{GRID} is needed",
+            image.width, image.height);
+    }
+    Ok(())
+}
+
+
+fn luminance(rgba: [u8; 4]) -> f64
+{
+    0.299 * rgba[0] as f64 + 0.587 * rgba[1] as f64 + 0.114 * rgba[2] as f64
+}
+
+
+/// Adds a keyed pattern of small luminance changes carrying `id` to the image
+pub fn embed(image: &mut Image, id: u32, options: &WatermarkOptions) -> Result<()>
+{
+    check_size(image)?;
+    let bits = payload(&options.key, id);
+
+    for (cell, (bit, chip)) in pattern(&options.key).into_iter().enumerate()
+    {
+        let delta = (chip * bits[bit] * options.strength as f64) as i16;
+
+        for y in cell_range(cell / GRID, image.height)
+        {
+            for x in cell_range(cell % GRID, image.width)
+            {
+                let mut rgba = image.pixel(x, y);
+                for channel in &mut rgba[..3]
+                {
+                    *channel = (*channel as i16 + delta).clamp(0, 255) as u8;
+                }
+                image.set_pixel(x, y, rgba);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Mean luminance of every cell minus the mean of its neighbours, which removes most of the
+/// image content and keeps the pattern
+fn residuals(image: &Image) -> Vec<f64>
+{
+    let mut means = vec![0.0; GRID * GRID];
+    for (cell, mean) in means.iter_mut().enumerate()
+    {
+        let (mut sum, mut count) = (0.0, 0);
+        for y in cell_range(cell / GRID, image.height)
+        {
+            for x in cell_range(cell % GRID, image.width)
+            {
+                sum += luminance(image.pixel(x, y));
+                count += 1;
+            }
+        }
+        *mean = sum / count as f64;
+    }
+
+    (0..GRID * GRID).map(|cell| {
+        let (row, column) = (cell / GRID, cell % GRID);
+        let neighbours: Vec<f64> = [(0, -1), (0, 1), (-1, 0), (1, 0)]
+            .iter()
+            .filter_map(|(dy, dx)| {
+                let (y, x) = (row as isize + dy, column as isize + dx);
+                let inside = (0..GRID as isize).contains(&y) && (0..GRID as isize).contains(&x);
+                inside.then(|| means[y as usize * GRID + x as usize])
+            })
+            .collect();
+
+        means[cell] - neighbours.iter().sum::<f64>() / neighbours.len() as f64
+    }).collect()
+}
+
+
+/// Standard normal cumulative distribution, from the erf approximation 7.1.26 of Abramowitz and Stegun
+fn normal_cdf(z: f64) -> f64
+{
+    let x = z.abs() / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1.0 - poly * (-x * x).exp();
+
+    if z >= 0.0 { (1.0 + erf) / 2.0 } else { (1.0 - erf) / 2.0 }
+}
+
+
+/// Reads the ID of a watermark embedded with the same key, and how sure the match is.
+/// The ID is read from the sign of each bit's correlation, and the check bits it implies
+/// are scored against theirs, which average to zero in an unmarked image
+pub fn detect(image: &Image, options: &WatermarkOptions) -> Result<Detection>
+{
+    check_size(image)?;
+    let residuals = residuals(image);
+
+    let deviation = (residuals.iter().map(|r| r * r).sum::<f64>() / residuals.len() as f64).sqrt();
+    if deviation == 0.0
+    {
+        return Ok(Detection {id: 0, z_score: 0.0, confidence: 0.0, detected: false});
+    }
+
+    let mut sums = [0.0; BITS];
+    let mut counts = [0; BITS];
+    for ((bit, chip), residual) in pattern(&options.key).into_iter().zip(&residuals)
+    {
+        sums[bit] += chip * residual;
+        counts[bit] += 1;
+    }
+    let scores: Vec<f64> = sums.iter().zip(counts).map(|(sum, count)| sum / (deviation * (count as f64).sqrt())).collect();
+
+    let id = scores[..ID_BITS].iter().fold(0, |id, score| (id << 1) | (*score > 0.0) as u32);
+    let expected = payload(&options.key, id);
+    let z_score = scores[ID_BITS..].iter().zip(&expected[ID_BITS..]).map(|(score, bit)| score * bit).sum::<f64>()
+        / (ID_BITS as f64).sqrt();
+
+    Ok(Detection {id, z_score, confidence: normal_cdf(z_score), detected: z_score >= THRESHOLD})
+}
+
+
+/// Watermarks the pixels of `png` and encodes them as 8-bit RGBA. Ancillary chunks are kept on
+/// their side of the image data, color space chunks included, except those describing the
+/// original sample format
+pub fn embed_png(png: &Png, id: u32, options: &WatermarkOptions) -> Result<Png>
+{
+    if png.is_animated()
+    {
+        bail!("Watermarking animated PNGs is not supported");
+    }
+
+    let mut image = pixels::decode(png)?;
+    embed(&mut image, id, options)?;
+    let encoded = image.to_png()?;
+
+    let idat = png.chunks().iter().position(|chunk| chunk.chunk_type().to_string() == "IDAT").unwrap_or(0);
+    let kept = |range: &[Chunk]| -> Vec<Chunk> {
+        range.iter()
+            .filter(|chunk| !chunk.chunk_type().is_critical())
+            .filter(|chunk| !DROPPED_CHUNK_TYPES.contains(&chunk.chunk_type().to_string().as_str()))
+            .cloned()
+            .collect()
+    };
+
+    let built = Png::builder()
+        .header(encoded.image_header()?)
+        .chunks(kept(&png.chunks()[..idat]))
+        .chunks(encoded.chunks_by_type("IDAT").cloned())
+        .chunks(kept(&png.chunks()[idat..]))
+        .build()?;
+
+    Ok(built)
+}
+
+
+/// Decodes the pixels of `png` and looks for a watermark
+pub fn detect_png(png: &Png, options: &WatermarkOptions) -> Result<Detection>
+{
+    detect(&pixels::decode(png)?, options)
+}
+
+
+impl fmt::Display for Detection
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        if self.detected
+        {
+            write!(f, "Watermark {} detected, confidence {:.6} (z = {:.1})", self.id, self.confidence, self.z_score)
+        }
+        else
+        {
+            write!(f, "No watermark detected, confidence {:.6} (z = {:.1})", self.confidence, self.z_score)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Smooth gradients with some texture, loosely like a photograph
+    fn testing_image(width: u32, height: u32) -> Image {
+        let mut rng = Rng::new("texture", 0);
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let base = 60.0 + 120.0 * x as f64 / width as f64 + 40.0 * (y as f64 / 9.0).sin();
+                let noise = (rng.next() % 9) as f64 - 4.0;
+                let value = (base + noise).clamp(0.0, 255.0) as u8;
+                image.set_pixel(x, y, [value, value / 2 + 40, 255 - value, 255]);
+            }
+        }
+        image
+    }
+
+    /// Bilinear resampling to a new size
+    fn resize(image: &Image, width: u32, height: u32) -> Image {
+        let mut resized = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let sx = ((x as f64 + 0.5) * image.width as f64 / width as f64 - 0.5).max(0.0);
+                let sy = ((y as f64 + 0.5) * image.height as f64 / height as f64 - 0.5).max(0.0);
+                let (x0, y0) = (sx as u32, sy as u32);
+                let (x1, y1) = ((x0 + 1).min(image.width - 1), (y0 + 1).min(image.height - 1));
+                let (fx, fy) = (sx - x0 as f64, sy - y0 as f64);
+
+                let mut rgba = [0; 4];
+                for (c, value) in rgba.iter_mut().enumerate() {
+                    let top = image.pixel(x0, y0)[c] as f64 * (1.0 - fx) + image.pixel(x1, y0)[c] as f64 * fx;
+                    let bottom = image.pixel(x0, y1)[c] as f64 * (1.0 - fx) + image.pixel(x1, y1)[c] as f64 * fx;
+                    *value = (top * (1.0 - fy) + bottom * fy).round() as u8;
+                }
+                resized.set_pixel(x, y, rgba);
+            }
+        }
+        resized
+    }
+
+    #[test]
+    fn test_detect() {
+        let options = WatermarkOptions::new("testing key");
+        let mut image = testing_image(320, 240);
+        assert!(!detect(&image, &options).unwrap().detected);
+
+        embed(&mut image, 0xc0ffee, &options).unwrap();
+        let detection = detect(&image, &options).unwrap();
+        assert!(detection.detected, "{detection}");
+        assert_eq!(detection.id, 0xc0ffee);
+        assert!(detection.confidence > 0.9999);
+
+        let other_key = WatermarkOptions {key: "other".to_string(), ..options};
+        assert!(!detect(&image, &other_key).unwrap().detected);
+    }
+
+    #[test]
+    fn test_detect_after_resize_and_noise() {
+        let options = WatermarkOptions::new("testing key");
+        let mut image = testing_image(400, 300);
+        embed(&mut image, 42, &options).unwrap();
+
+        let mut resized = resize(&image, 330, 250);
+        let mut rng = Rng::new("noise", 0);
+        for value in resized.pixels.iter_mut() {
+            *value = (*value as i16 + (rng.next() % 5) as i16 - 2).clamp(0, 255) as u8;
+        }
+
+        let detection = detect(&resized, &options).unwrap();
+        assert!(detection.detected, "{detection}");
+        assert_eq!(detection.id, 42);
+    }
+
+    #[test]
+    fn test_embed_png() {
+        let mut png = testing_image(128, 128).to_png().unwrap();
+        png.append_chunk(Chunk::new("tEXt".parse().unwrap(), b"Comment\0kept".to_vec()));
+        png.insert_chunk(1, Chunk::new("gAMA".parse().unwrap(), vec![0, 0, 0xb1, 0x8f]));
+        png.insert_chunk(2, Chunk::new("sBIT".parse().unwrap(), vec![5, 6, 5, 8]));
+
+        let marked = embed_png(&png, 7, &WatermarkOptions::new("testing key")).unwrap();
+        let marked = Png::try_from(&marked.as_bytes()[..]).unwrap();
+        assert!(marked.chunk_by_type("tEXt").is_some());
+        assert_eq!(marked.chunks()[1].chunk_type().to_string(), "gAMA");
+        assert!(marked.chunk_by_type("sBIT").is_none());
+        assert_eq!(detect_png(&marked, &WatermarkOptions::new("testing key")).unwrap().id, 7);
+
+        assert!(detect(&Image::new(32, 32), &WatermarkOptions::new("testing key")).is_err());
+    }
+
+    #[test]
+    fn test_normal_cdf() {
+        assert!((normal_cdf(0.0) - 0.5).abs() < 1e-6);
+        assert!((normal_cdf(1.96) - 0.975).abs() < 1e-3);
+        assert!((normal_cdf(-1.96) - 0.025).abs() < 1e-3);
+    }
+}